@@ -0,0 +1,28 @@
+use banker::{bank::Bank, config::BankConfig};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// Compare looking up a client's transactions through the client index against scanning every
+// recorded transaction, as the number of transactions grows.
+fn client_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("client_lookup");
+
+    for transactions in [1_000, 10_000, 100_000] {
+        let mut bank = Bank::with_config(BankConfig {
+            enable_client_index: true,
+            ..BankConfig::default()
+        });
+        bank.apply_bulk_credit((0..transactions).map(|i| ((i % 100) as u16, Default::default())));
+
+        group.bench_with_input(BenchmarkId::new("index", transactions), &bank, |b, bank| {
+            b.iter(|| bank.client_transaction_ids(black_box(7)).map(<[_]>::len))
+        });
+        group.bench_with_input(BenchmarkId::new("scan", transactions), &bank, |b, bank| {
+            b.iter(|| bank.transaction_ids_for_client(black_box(7)).count())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, client_lookup);
+criterion_main!(benches);
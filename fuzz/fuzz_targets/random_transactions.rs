@@ -0,0 +1,18 @@
+#![no_main]
+
+use banker::{bank::Bank, transaction::Transaction};
+use libfuzzer_sys::fuzz_target;
+
+// Any sequence of transactions must be processed without panicking, and leave the bank in a
+// consistent state.
+fuzz_target!(|transactions: Vec<Transaction>| {
+    let mut bank = Bank::new();
+    bank.process_all(transactions);
+
+    if let Err(e) = bank.validate_consistency() {
+        panic!("inconsistent bank: {}", e);
+    }
+    if let Err(e) = bank.reconcile() {
+        panic!("bank does not reconcile: {}", e);
+    }
+});
@@ -1,12 +1,50 @@
-use crate::amount::Amount;
+use crate::{
+    amount::Amount,
+    bank::{AccountID, DisputeResolution, TransactionID},
+    error::AccountError,
+};
 
 /// A basic Bank account. A single `Account` will store the amount available, held, and if the
 /// account is locked or not.
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Account {
     available: Amount,
     held: Amount,
     locked: bool,
+
+    // Set by an administrative freeze. Unlike `locked` this is reversible, and blocks every
+    // transaction rather than only deposits and withdrawals.
+    frozen: bool,
+
+    // Set when an operator has corrected this account, e.g. through an admin reversal.
+    flagged_for_review: bool,
+
+    // Every chargeback applied to this account, in the order they happened.
+    chargebacks: Vec<(TransactionID, Amount)>,
+
+    // Risk counters of disputes and chargebacks, which unlike the history can be reset by an
+    // administrator, e.g. after a review period.
+    dispute_count: usize,
+    chargeback_count: usize,
+
+    // Disputes which were closed with a recorded reason, in the order they were closed.
+    dispute_history: Vec<(TransactionID, DisputeResolution)>,
+
+    // The account this is a sub-account of, for corporate account groups.
+    parent_id: Option<AccountID>,
+
+    // How far below zero withdrawals may take the available funds. Without a limit the available
+    // funds can not go negative.
+    withdrawal_limit: Option<Amount>,
+
+    // The available funds debits may not take the account below, e.g. for savings accounts.
+    minimum_balance: Option<Amount>,
+
+    // Sum of all fees charged to this account, e.g. through `Bank::apply_percentage_fee`.
+    total_fees_paid: Amount,
+
+    // How many transactions have been successfully applied to this account.
+    transaction_count: usize,
 }
 
 impl Account {
@@ -15,9 +53,53 @@ impl Account {
             available: Amount::new(),
             held: Amount::new(),
             locked: false,
+            frozen: false,
+            flagged_for_review: false,
+            chargebacks: Vec::new(),
+            dispute_count: 0,
+            chargeback_count: 0,
+            dispute_history: Vec::new(),
+            parent_id: None,
+            withdrawal_limit: None,
+            minimum_balance: None,
+            total_fees_paid: Amount::new(),
+            transaction_count: 0,
         }
     }
 
+    /// Create an account with the given balances, e.g. when restoring from a snapshot.
+    pub(crate) fn with_balances(available: Amount, held: Amount, locked: bool) -> Self {
+        Self {
+            available,
+            held,
+            locked,
+            ..Self::new()
+        }
+    }
+
+    /// Create a new, empty sub-account of `parent`.
+    pub fn with_parent(parent: AccountID) -> Self {
+        Self {
+            parent_id: Some(parent),
+            ..Self::new()
+        }
+    }
+
+    /// Reinitialize the account to the same state as `Account::new()`, reusing any allocations.
+    #[cfg(test)]
+    pub fn reset(&mut self) -> &mut Self {
+        let mut chargebacks = std::mem::take(&mut self.chargebacks);
+        let mut dispute_history = std::mem::take(&mut self.dispute_history);
+        chargebacks.clear();
+        dispute_history.clear();
+        *self = Self {
+            chargebacks,
+            dispute_history,
+            ..Self::new()
+        };
+        self
+    }
+
     pub fn available(&self) -> Amount {
         self.available
     }
@@ -30,47 +112,246 @@ impl Account {
         self.held + self.available
     }
 
+    /// The balances of the account as `(available, held, total, locked)`, in the same order as
+    /// the columns of `Bank::export_csv`.
+    pub fn as_balance_tuple(&self) -> (Amount, Amount, Amount, bool) {
+        (self.available, self.held, self.total(), self.locked)
+    }
+
+    /// The most that could currently be moved into held funds by a dispute. Funds which have left
+    /// the account since a deposit can not be disputed, and an account below zero has nothing to
+    /// dispute.
+    pub fn available_for_dispute(&self) -> Amount {
+        let disputable = if self.available < self.total() {
+            self.available
+        } else {
+            self.total()
+        };
+        if disputable.is_negative() {
+            Amount::new()
+        } else {
+            disputable
+        }
+    }
+
     pub fn is_locked(&self) -> bool {
         self.locked
     }
 
-    pub fn credit(&mut self, val: Amount) {
-        self.available += val;
+    /// Disputes which were closed with a recorded reason, in the order they were closed.
+    pub fn dispute_history(&self) -> &[(TransactionID, DisputeResolution)] {
+        &self.dispute_history
     }
 
-    pub fn try_debit(&mut self, val: Amount) -> Result<(), ()> {
-        if self.available >= val {
-            self.available -= val;
-            return Ok(());
+    pub(crate) fn record_dispute_resolution(
+        &mut self,
+        tx: TransactionID,
+        resolution: DisputeResolution,
+    ) {
+        self.dispute_history.push((tx, resolution));
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Administratively freeze the account, blocking all transactions until it is unfrozen.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn unfreeze(&mut self) {
+        self.frozen = false;
+    }
+
+    pub fn parent_id(&self) -> Option<AccountID> {
+        self.parent_id
+    }
+
+    pub fn withdrawal_limit(&self) -> Option<Amount> {
+        self.withdrawal_limit
+    }
+
+    pub fn set_withdrawal_limit(&mut self, limit: Option<Amount>) {
+        self.withdrawal_limit = limit;
+    }
+
+    pub fn minimum_balance(&self) -> Option<Amount> {
+        self.minimum_balance
+    }
+
+    pub fn set_minimum_balance(&mut self, minimum: Option<Amount>) {
+        self.minimum_balance = minimum;
+    }
+
+    pub(crate) fn set_parent_id(&mut self, parent: Option<AccountID>) {
+        self.parent_id = parent;
+    }
+
+    pub fn is_flagged_for_review(&self) -> bool {
+        self.flagged_for_review
+    }
+
+    pub(crate) fn lock(&mut self) {
+        self.locked = true;
+    }
+
+    /// Mark the account as requiring human review.
+    pub fn flag_for_review(&mut self) {
+        self.flagged_for_review = true;
+    }
+
+    /// Every chargeback applied to this account, in the order they happened.
+    pub fn chargeback_history(&self) -> &[(TransactionID, Amount)] {
+        &self.chargebacks
+    }
+
+    /// How many chargebacks have been applied to this account since `reset_chargeback_count`.
+    pub fn chargeback_count(&self) -> usize {
+        self.chargeback_count
+    }
+
+    /// How many disputes have been opened on this account since `reset_dispute_count`.
+    pub fn dispute_count(&self) -> usize {
+        self.dispute_count
+    }
+
+    /// Admin operation to give the account a clean slate on its dispute count. The disputes
+    /// themselves are not affected.
+    pub fn reset_dispute_count(&mut self) {
+        self.dispute_count = 0;
+    }
+
+    /// Admin operation to give the account a clean slate on its chargeback count. The chargeback
+    /// history is kept.
+    pub fn reset_chargeback_count(&mut self) {
+        self.chargeback_count = 0;
+    }
+
+    pub fn total_fees_paid(&self) -> Amount {
+        self.total_fees_paid
+    }
+
+    /// How many transactions have been successfully applied to this account.
+    pub fn transaction_count(&self) -> usize {
+        self.transaction_count
+    }
+
+    /// Whether exactly one transaction has been applied to this account, i.e. its first deposit.
+    pub fn is_new(&self) -> bool {
+        self.transaction_count == 1
+    }
+
+    pub(crate) fn record_applied_transaction(&mut self) {
+        self.transaction_count += 1;
+    }
+
+    pub(crate) fn record_fee(&mut self, fee: Amount) {
+        self.total_fees_paid = self.total_fees_paid.saturating_add(fee);
+    }
+
+    /// Credit `val` to the available funds, returning the new available balance. Negative amounts
+    /// are rejected, since they would bypass the checks of `try_debit`.
+    pub fn try_credit(&mut self, val: Amount) -> Result<Amount, AccountError> {
+        if self.locked {
+            return Err(AccountError::AccountLocked);
+        }
+        if val.is_negative() {
+            return Err(AccountError::NegativeAmount);
+        }
+        // The total must stay representable too, since held funds can be moved back to available.
+        let available = self
+            .available
+            .checked_add(val)
+            .filter(|available| available.checked_add(self.held).is_some())
+            .ok_or(AccountError::OverflowError)?;
+        self.available = available;
+        Ok(self.available)
+    }
+
+    pub fn try_debit(&mut self, val: Amount) -> Result<(), AccountError> {
+        if self.locked {
+            return Err(AccountError::AccountLocked);
+        }
+        let floor = -self.withdrawal_limit.unwrap_or_default();
+        match self.available.checked_sub(val) {
+            Some(available) if available >= floor => match self.minimum_balance {
+                Some(minimum) if available < minimum => {
+                    Err(AccountError::MinimumBalanceViolation {
+                        minimum,
+                        would_result_in: available,
+                    })
+                }
+                _ => {
+                    self.available = available;
+                    Ok(())
+                }
+            },
+            _ => Err(AccountError::InsufficientFunds),
+        }
+    }
+
+    /// Credit (positive `delta`) or debit (negative `delta`) the available funds, even if the
+    /// account is locked. The available funds still can not go further below zero than the
+    /// withdrawal limit allows.
+    pub(crate) fn try_adjust(&mut self, delta: Amount) -> Result<(), AccountError> {
+        let available = self
+            .available
+            .checked_add(delta)
+            .filter(|available| available.checked_add(self.held).is_some())
+            .ok_or(AccountError::OverflowError)?;
+        if available < -self.withdrawal_limit.unwrap_or_default() {
+            return Err(AccountError::InsufficientFunds);
         }
-        return Err(());
+        self.available = available;
+        Ok(())
     }
 
-    pub fn try_dispute(&mut self, val: Amount) -> Result<(), ()> {
-        if self.available >= val {
+    /// Move `val` from available into held funds, as long as it is at most
+    /// `available_for_dispute`.
+    pub fn try_dispute(&mut self, val: Amount) -> Result<(), AccountError> {
+        if val <= self.available_for_dispute() {
             self.available -= val;
             self.held += val;
+            self.dispute_count += 1;
             return Ok(());
         }
-        return Err(());
+        Err(AccountError::InsufficientFunds)
+    }
+
+    pub fn try_resolve(&mut self, val: Amount) -> Result<(), AccountError> {
+        self.release_held(val)
+    }
+
+    /// Release only part of a disputed amount back to available, leaving the remainder held. Used
+    /// when a dispute is partially settled, e.g. a merchant refunding part of a charge. The amount
+    /// must be positive.
+    pub fn try_partial_resolve(&mut self, amount: Amount) -> Result<(), AccountError> {
+        if amount <= Amount::new() {
+            return Err(AccountError::NonPositiveAmount);
+        }
+        self.release_held(amount)
     }
 
-    pub fn try_resolve(&mut self, val: Amount) -> Result<(), ()> {
+    // Move `val` from held back to available.
+    fn release_held(&mut self, val: Amount) -> Result<(), AccountError> {
         if self.held >= val {
             self.held -= val;
             self.available += val;
             return Ok(());
         }
-        return Err(());
+        Err(AccountError::InsufficientHeld)
     }
 
-    pub fn try_chargeback(&mut self, val: Amount) -> Result<(), ()> {
+    pub fn try_chargeback(&mut self, tx: TransactionID, val: Amount) -> Result<(), AccountError> {
         if self.held >= val {
             self.held -= val;
             self.locked = true;
+            self.chargebacks.push((tx, val));
+            self.chargeback_count += 1;
             return Ok(());
         }
-        return Err(());
+        Err(AccountError::InsufficientHeld)
     }
 }
 
@@ -81,16 +362,66 @@ mod tests {
     #[test]
     fn credit() {
         let mut account = Account::new();
-        account.credit(1000.into());
-        account.credit(3000.into());
+        assert_eq!(account.try_credit(1000.into()), Ok(1000.into()));
+        assert_eq!(account.try_credit(3000.into()), Ok(4000.into()));
+        assert_eq!(
+            account.try_credit((-1000).into()),
+            Err(AccountError::NegativeAmount)
+        );
         assert_eq!(account.available, 4000.into());
         assert!(!account.locked)
     }
 
+    #[test]
+    fn credit_overflow() {
+        let mut account = Account::new();
+        account.try_credit(i64::MAX.into()).unwrap();
+        assert_eq!(
+            account.try_credit(1.into()),
+            Err(AccountError::OverflowError)
+        );
+        assert_eq!(account.available, i64::MAX.into());
+    }
+
+    #[test]
+    fn credit_locked() {
+        let mut account = Account::new();
+        account.try_credit(1000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
+        assert_eq!(
+            account.try_credit(1000.into()),
+            Err(AccountError::AccountLocked)
+        );
+        assert_eq!(account.total(), 0.into());
+    }
+
+    #[test]
+    fn reset() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
+        account.set_parent_id(Some(7));
+        account.set_withdrawal_limit(Some(500.into()));
+        account.set_minimum_balance(Some(100.into()));
+
+        account.reset().try_credit(1000.into()).unwrap();
+        assert_eq!(account.parent_id(), None);
+        assert_eq!(account.withdrawal_limit(), None);
+        assert_eq!(account.minimum_balance(), None);
+        assert_eq!(account.available, 1000.into());
+        assert_eq!(account.held, 0.into());
+        assert!(!account.locked);
+
+        account.reset();
+        assert_eq!(account, Account::new());
+    }
+
     #[test]
     fn debit() {
         let mut account = Account::new();
-        account.credit(1000.into());
+        account.try_credit(1000.into()).unwrap();
         account.try_debit(500.into()).unwrap();
         assert_eq!(account.available, 500.into());
         assert!(!account.locked)
@@ -99,15 +430,73 @@ mod tests {
     #[test]
     fn debit_fail() {
         let mut account = Account::new();
-        account.credit(1000.into());
+        account.try_credit(1000.into()).unwrap();
         account.try_debit(1500.into()).unwrap_err();
         assert!(!account.locked)
     }
 
+    #[test]
+    fn debit_locked() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
+        assert_eq!(
+            account.try_debit(1000.into()),
+            Err(AccountError::AccountLocked)
+        );
+        assert_eq!(account.available, 2000.into());
+    }
+
+    #[test]
+    fn debit_withdrawal_limit() {
+        let mut account = Account::new();
+        account.set_withdrawal_limit(Some(500.into()));
+        account.try_credit(1000.into()).unwrap();
+        account.try_debit(1500.into()).unwrap();
+        assert_eq!(account.available, (-500).into());
+        account.try_debit(1.into()).unwrap_err();
+        assert_eq!(account.available, (-500).into());
+    }
+
+    #[test]
+    fn as_balance_tuple() {
+        let mut account = Account::new();
+        account.try_credit(1000.into()).unwrap();
+        account.try_dispute(300.into()).unwrap();
+
+        let (available, held, total, locked) = account.as_balance_tuple();
+        assert_eq!(available, account.available());
+        assert_eq!(held, account.held());
+        assert_eq!(total, account.total());
+        assert_eq!(locked, account.is_locked());
+        assert_eq!(
+            account.as_balance_tuple(),
+            (700.into(), 300.into(), 1000.into(), false)
+        );
+    }
+
+    #[test]
+    fn debit_minimum_balance() {
+        let mut account = Account::new();
+        account.set_minimum_balance(Some(100.into()));
+        account.try_credit(150.into()).unwrap();
+        account.try_debit(50.into()).unwrap();
+        assert_eq!(account.available, 100.into());
+        assert_eq!(
+            account.try_debit(1.into()),
+            Err(AccountError::MinimumBalanceViolation {
+                minimum: 100.into(),
+                would_result_in: 99.into(),
+            })
+        );
+        assert_eq!(account.available, 100.into());
+    }
+
     #[test]
     fn dispute() {
         let mut account = Account::new();
-        account.credit(1000.into());
+        account.try_credit(1000.into()).unwrap();
         account.try_dispute(1000.into()).unwrap();
         assert_eq!(account.total(), 1000.into());
         assert_eq!(account.held, 1000.into());
@@ -118,16 +507,32 @@ mod tests {
     #[test]
     fn dispute_fail() {
         let mut account = Account::new();
-        account.credit(1000.into());
+        account.try_credit(1000.into()).unwrap();
         account.try_dispute(2000.into()).unwrap_err();
         assert!(!account.locked)
     }
 
+    #[test]
+    fn available_for_dispute() {
+        let mut account = Account::new();
+        account.try_credit(1000.into()).unwrap();
+        account.try_debit(600.into()).unwrap();
+        assert_eq!(account.available_for_dispute(), 400.into());
+
+        account.set_withdrawal_limit(Some(500.into()));
+        account.try_debit(700.into()).unwrap();
+        assert_eq!(account.available_for_dispute(), 0.into());
+        assert_eq!(
+            account.try_dispute(1.into()),
+            Err(AccountError::InsufficientFunds)
+        );
+    }
+
     #[test]
     fn resolve() {
         let mut account = Account::new();
-        account.credit(1000.into());
-        account.credit(2000.into());
+        account.try_credit(1000.into()).unwrap();
+        account.try_credit(2000.into()).unwrap();
         account.try_dispute(1000.into()).unwrap();
         account.try_resolve(1000.into()).unwrap();
         assert_eq!(account.total(), 3000.into());
@@ -139,33 +544,109 @@ mod tests {
     #[test]
     fn resolve_fail() {
         let mut account = Account::new();
-        account.credit(1000.into());
-        account.credit(2000.into());
+        account.try_credit(1000.into()).unwrap();
+        account.try_credit(2000.into()).unwrap();
         account.try_dispute(1000.into()).unwrap();
         account.try_resolve(2000.into()).unwrap_err();
         assert!(!account.locked)
     }
 
+    #[test]
+    fn partial_resolve() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(2000.into()).unwrap();
+        account.try_partial_resolve(500.into()).unwrap();
+        assert_eq!(account.total(), 3000.into());
+        assert_eq!(account.held, 1500.into());
+        assert_eq!(account.available, 1500.into());
+        assert!(!account.locked)
+    }
+
+    #[test]
+    fn partial_resolve_fail() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        assert_eq!(
+            account.try_partial_resolve(1500.into()),
+            Err(AccountError::InsufficientHeld)
+        );
+        for amount in [Amount::new(), (-500).into()] {
+            assert_eq!(
+                account.try_partial_resolve(amount),
+                Err(AccountError::NonPositiveAmount)
+            );
+        }
+        assert_eq!(account.held, 1000.into());
+        assert_eq!(account.available, 2000.into());
+        assert!(!account.locked)
+    }
+
     #[test]
     fn chargeback() {
         let mut account = Account::new();
-        account.credit(1000.into());
-        account.credit(2000.into());
+        account.try_credit(1000.into()).unwrap();
+        account.try_credit(2000.into()).unwrap();
         account.try_dispute(1000.into()).unwrap();
-        account.try_chargeback(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
         assert_eq!(account.total(), 2000.into());
         assert_eq!(account.held, 0.into());
         assert_eq!(account.available, 2000.into());
         assert!(account.locked)
     }
 
+    #[test]
+    fn chargeback_history() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_resolve(1000.into()).unwrap();
+        assert!(account.chargeback_history().is_empty());
+
+        account.try_dispute(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
+        account.try_dispute(500.into()).unwrap();
+        account.try_chargeback(2, 500.into()).unwrap();
+        account.try_dispute(500.into()).unwrap();
+        account.try_resolve(500.into()).unwrap();
+
+        assert_eq!(
+            account.chargeback_history(),
+            &[(1, 1000.into()), (2, 500.into())]
+        );
+    }
+
+    #[test]
+    fn reset_risk_counters() {
+        let mut account = Account::new();
+        account.try_credit(3000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_resolve(1000.into()).unwrap();
+        account.try_dispute(1000.into()).unwrap();
+        account.try_chargeback(1, 1000.into()).unwrap();
+        assert_eq!(account.dispute_count(), 2);
+        assert_eq!(account.chargeback_count(), 1);
+
+        account.reset_dispute_count();
+        assert_eq!(account.dispute_count(), 0);
+        assert_eq!(account.chargeback_count(), 1);
+
+        account.try_dispute(500.into()).unwrap();
+        account.try_chargeback(2, 500.into()).unwrap();
+        account.reset_chargeback_count();
+        assert_eq!(account.dispute_count(), 1);
+        assert_eq!(account.chargeback_count(), 0);
+        assert_eq!(account.chargeback_history().len(), 2);
+    }
+
     #[test]
     fn chargeback_fail() {
         let mut account = Account::new();
-        account.credit(1000.into());
-        account.credit(2000.into());
+        account.try_credit(1000.into()).unwrap();
+        account.try_credit(2000.into()).unwrap();
         account.try_dispute(1000.into()).unwrap();
-        account.try_chargeback(2000.into()).unwrap_err();
+        account.try_chargeback(1, 2000.into()).unwrap_err();
         assert!(!account.locked)
     }
 }
@@ -1,53 +1,320 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-/// A decimal value intended to be precise up to four decimal places. The underlying storage of
-/// this floating point number is a u64, meaning the maximum value of a bank account would be
-/// 1844674407370955.1615 (represented as 2 ^ 64).
+use crate::error::{ParseAmountError, RatioError, SplitError};
+
+/// A decimal value intended to be precise up to `PRECISION` decimal places, four by default. The
+/// underlying storage of this floating point number is an i64, meaning the maximum value of a bank
+/// account with four decimal places would be 922337203685477.5807 (represented as 2 ^ 63 - 1).
+/// Negative values are representable so that accounts with a withdrawal limit can go below zero,
+/// and so that amounts can be interchanged with systems that use signed integers.
+///
+/// Precisions of 2 (cents), 4, and 8 (crypto) decimal places are supported. Amounts of different
+/// precisions can not be mixed, and must be converted explicitly with `checked_to_precision` or
+/// `to_precision_lossy`.
 ///
-/// This type currently supports basic add and subtraction, and will need an extension on it's api
-/// if we want to handle overflows in the future. We can also change the underlying storage to
-/// allocate more bits for larger account maximums or to handle negative values if we want to allow
-/// accounts to be negative.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
-pub struct Amount(u64);
+/// The `+` and `-` operators overflow like plain `i64` arithmetic, so they panic in debug builds.
+/// Use the `checked_*`, `saturating_*`, or `wrapping_*` variants where amounts can approach the
+/// limits. We can also change the underlying
+/// storage to allocate more bits for larger account maximums.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Amount<const PRECISION: u32 = 4>(i64);
+
+impl<const PRECISION: u32> Amount<PRECISION> {
+    // The raw value of one whole unit.
+    const SCALE: i64 = 10_i64.pow(PRECISION);
+
+    /// The largest representable amount.
+    pub const MAX: Self = Self(i64::MAX);
+
+    /// The smallest representable amount.
+    pub const MIN: Self = Self(i64::MIN);
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Convert to a different precision, returning `None` if the value can not be represented
+    /// exactly, i.e. if it would overflow or lose decimal places.
+    pub fn checked_to_precision<const TO: u32>(&self) -> Option<Amount<TO>> {
+        if TO >= PRECISION {
+            let factor = 10_i64.checked_pow(TO - PRECISION)?;
+            self.0.checked_mul(factor).map(Amount)
+        } else {
+            let factor = 10_i64.pow(PRECISION - TO);
+            (self.0 % factor == 0).then(|| Amount(self.0 / factor))
+        }
+    }
+
+    /// Convert to a different precision, rounding half away from zero if decimal places are lost.
+    /// Prefer `checked_to_precision` unless losing precision is intended.
+    ///
+    /// Panics if the value can not be represented.
+    #[must_use = "converting to a lower precision may round the amount"]
+    pub fn to_precision_lossy<const TO: u32>(&self) -> Amount<TO> {
+        if TO >= PRECISION {
+            return self.checked_to_precision().expect("amount overflow");
+        }
+
+        let factor = 10_i64.pow(PRECISION - TO);
+        let half = factor / 2;
+        if self.0 >= 0 {
+            Amount(self.0 / factor + i64::from(self.0 % factor >= half))
+        } else {
+            Amount(self.0 / factor - i64::from(-(self.0 % factor) >= half))
+        }
+    }
 
+    /// Format the amount like `Display`, optionally grouping the whole part into thousands with
+    /// commas, e.g. `1,234.5678`.
+    pub fn to_display_string(&self, use_thousands_separator: bool) -> String {
+        let plain = self.to_string();
+        if !use_thousands_separator {
+            return plain;
+        }
+
+        let (sign, digits) = match plain.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", plain.as_str()),
+        };
+        let (whole, fraction) = match digits.find('.') {
+            Some(dot) => digits.split_at(dot),
+            None => (digits, ""),
+        };
+
+        let mut grouped = String::with_capacity(plain.len() + whole.len() / 3);
+        grouped.push_str(sign);
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        grouped.push_str(fraction);
+        grouped
+    }
+
+    /// Divide the amount into `parts` shares which sum exactly to the original amount. When the
+    /// amount does not divide evenly, the remaining smallest units are handed out one at a time to
+    /// the first shares.
+    pub fn split(&self, parts: u32) -> Result<Vec<Self>, SplitError> {
+        if parts == 0 {
+            return Err(SplitError::ZeroParts);
+        }
+
+        let parts = i64::from(parts);
+        let share = self.0 / parts;
+        let remainder = self.0 % parts;
+
+        Ok((0..parts)
+            .map(|i| {
+                if i < remainder.abs() {
+                    Self(share + remainder.signum())
+                } else {
+                    Self(share)
+                }
+            })
+            .collect())
+    }
+
+    /// `pct` percent of the amount, rounded half away from zero to the amount's precision. `pct`
+    /// is clamped to `[0.0, 100.0]`, so the result is never larger than the amount itself.
+    #[must_use]
+    pub fn percentage(&self, pct: f64) -> Self {
+        let fraction = pct.clamp(0.0, 100.0) / 100.0;
+        Self((self.0 as f64 * fraction).round() as i64)
+    }
+
+    /// Add two amounts, returning `None` if the result can not be represented.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Subtract two amounts, returning `None` if the result can not be represented.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Add two amounts, clamping to `Amount::MAX` or `Amount::MIN` on overflow.
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    /// Subtract two amounts, clamping to `Amount::MAX` or `Amount::MIN` on overflow.
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Add two amounts, wrapping around on overflow. Useful for sums whose intermediate values may
+    /// overflow even though the final result does not.
+    pub fn wrapping_add(self, other: Self) -> Self {
+        Self(self.0.wrapping_add(other.0))
+    }
+
+    pub fn wrapping_sub(self, other: Self) -> Self {
+        Self(self.0.wrapping_sub(other.0))
+    }
+}
+
+// Constructors and unit conversions live on the default precision, so that e.g. `Amount::new()`
+// does not need a type annotation. Other precisions can use `Default` or a conversion.
 impl Amount {
     pub fn new() -> Self {
         Amount(0)
     }
+
+    /// Create an amount of `numerator / denominator` whole units, rounded half away from zero to
+    /// four decimal places. Rounding means ratios do not always add up, e.g. three times `1 / 3`
+    /// is `0.9999` rather than `1.0000`; use `split` to divide an amount without losing any of it.
+    pub fn from_ratio(numerator: i64, denominator: i64) -> Result<Self, RatioError> {
+        if denominator == 0 {
+            return Err(RatioError::DivisionByZero);
+        }
+
+        let scaled = i128::from(numerator) * i128::from(Self::SCALE);
+        let denominator = i128::from(denominator);
+        let mut quotient = scaled / denominator;
+        let remainder = scaled % denominator;
+        if 2 * remainder.abs() >= denominator.abs() {
+            quotient += scaled.signum() * denominator.signum();
+        }
+        i64::try_from(quotient)
+            .map(Amount)
+            .map_err(|_| RatioError::Overflow)
+    }
+
+    /// Create an amount from an integer number of cents (hundredths of the base unit).
+    ///
+    /// Panics if the value can not be represented.
+    pub fn from_cents(cents: i64) -> Self {
+        Amount::<2>(cents)
+            .checked_to_precision()
+            .expect("amount overflow")
+    }
+
+    /// Convert to an integer number of cents, rounding half away from zero to the nearest cent.
+    pub fn to_cents(&self) -> i64 {
+        self.to_precision_lossy::<2>().0
+    }
+
+    /// Create an amount from an integer number of milliunits (thousandths of the base unit).
+    ///
+    /// Panics if the value can not be represented.
+    pub fn from_milliunits(mu: i64) -> Self {
+        Amount::<3>(mu)
+            .checked_to_precision()
+            .expect("amount overflow")
+    }
+
+    /// Convert to an integer number of milliunits, rounding half away from zero to the nearest
+    /// milliunit.
+    pub fn to_milliunits(&self) -> i64 {
+        self.to_precision_lossy::<3>().0
+    }
+}
+
+impl<const PRECISION: u32> fmt::Display for Amount<PRECISION> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let scale = Self::SCALE.unsigned_abs();
+        if PRECISION == 0 {
+            return write!(f, "{}{}", sign, abs);
+        }
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            abs / scale,
+            abs % scale,
+            width = PRECISION as usize
+        )
+    }
 }
 
-impl<'de> Deserialize<'de> for Amount {
-    fn deserialize<D>(deserializer: D) -> Result<Amount, D::Error>
+/// Parses a decimal string such as `"-12.34"`. Extra decimal places beyond `PRECISION` are rounded
+/// half away from zero.
+impl<const PRECISION: u32> FromStr for Amount<PRECISION> {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(ParseAmountError::Invalid);
+        }
+
+        let precision = PRECISION as usize;
+        let mut raw: i64 = 0;
+        for c in whole.chars().chain(
+            fraction
+                .chars()
+                .chain(std::iter::repeat('0'))
+                .take(precision),
+        ) {
+            raw = raw
+                .checked_mul(10)
+                .and_then(|r| r.checked_add(i64::from(c as u8 - b'0')))
+                .ok_or(ParseAmountError::Overflow)?;
+        }
+        if fraction
+            .as_bytes()
+            .get(precision)
+            .is_some_and(|c| *c >= b'5')
+        {
+            raw = raw.checked_add(1).ok_or(ParseAmountError::Overflow)?;
+        }
+
+        Ok(Self(if negative { -raw } else { raw }))
+    }
+}
+
+impl<'de, const PRECISION: u32> Deserialize<'de> for Amount<PRECISION> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct AmountVisitor;
+        struct AmountVisitor<const PRECISION: u32>;
 
-        impl<'de> de::Visitor<'de> for AmountVisitor {
-            type Value = u64;
+        impl<'de, const PRECISION: u32> de::Visitor<'de> for AmountVisitor<PRECISION> {
+            type Value = i64;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a floating point value precise up to four decimal places")
+                write!(
+                    formatter,
+                    "a floating point value precise up to {} decimal places",
+                    PRECISION
+                )
             }
 
             fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                Ok((value * 10000.0).round() as u64)
+                Ok((value * Amount::<PRECISION>::SCALE as f64).round() as i64)
             }
         }
 
-        let amount = deserializer.deserialize_f64(AmountVisitor)?;
+        let amount = deserializer.deserialize_f64(AmountVisitor::<PRECISION>)?;
 
         Ok(Self(amount))
     }
 }
 
-impl std::ops::Add for Amount {
+impl<const PRECISION: u32> std::ops::Add for Amount<PRECISION> {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
@@ -55,38 +322,69 @@ impl std::ops::Add for Amount {
     }
 }
 
-impl std::ops::AddAssign for Amount {
+impl<const PRECISION: u32> std::ops::AddAssign for Amount<PRECISION> {
     fn add_assign(&mut self, other: Self) {
         self.0 += other.0
     }
 }
 
-impl std::ops::SubAssign for Amount {
+impl<const PRECISION: u32> std::iter::Sum for Amount<PRECISION> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |acc, x| acc + x)
+    }
+}
+
+impl<const PRECISION: u32> std::ops::Sub for Amount<PRECISION> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl<const PRECISION: u32> std::ops::Neg for Amount<PRECISION> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl<const PRECISION: u32> std::ops::SubAssign for Amount<PRECISION> {
     fn sub_assign(&mut self, other: Self) {
         self.0 -= other.0
     }
 }
 
-impl Serialize for Amount {
+impl<const PRECISION: u32> Serialize for Amount<PRECISION> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_f64(self.0 as f64 / 10000.0)
+        serializer.serialize_f64(self.0 as f64 / Self::SCALE as f64)
+    }
+}
+
+// Amounts up to a million with four decimal places, so that generated transactions are mostly
+// applied instead of overflowing.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Amount {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self(u.int_in_range(0..=1_000_000 * 10_000)?))
     }
 }
 
 #[cfg(test)]
-impl From<u64> for Amount {
-    fn from(inner: u64) -> Self {
+impl<const PRECISION: u32> From<i64> for Amount<PRECISION> {
+    fn from(inner: i64) -> Self {
         Self(inner)
     }
 }
 
 #[cfg(test)]
-impl From<f64> for Amount {
+impl<const PRECISION: u32> From<f64> for Amount<PRECISION> {
     fn from(inner: f64) -> Self {
-        Self((inner * 10000.0).round() as u64)
+        Self((inner * Self::SCALE as f64).round() as i64)
     }
 }
 
@@ -94,6 +392,184 @@ impl From<f64> for Amount {
 mod tests {
     use super::*;
 
+    #[test]
+    fn checked() {
+        let max = Amount::<4>(i64::MAX);
+        assert_eq!(max.checked_add(Amount(1)), None);
+        assert_eq!(Amount::<4>(1).checked_add(Amount(2)), Some(Amount(3)));
+        assert_eq!(Amount::<4>(i64::MIN).checked_sub(Amount(1)), None);
+        assert_eq!(Amount::<4>(3).checked_sub(Amount(2)), Some(Amount(1)));
+    }
+
+    #[test]
+    fn saturating() {
+        let half = Amount::<4>(i64::MAX / 2 + 1);
+        assert_eq!(half.saturating_add(half), Amount::MAX);
+        assert_eq!((-half).saturating_sub(half), Amount::MIN);
+        assert_eq!((-half).saturating_sub(half + Amount(1)), Amount::MIN);
+        assert_eq!(Amount::MIN.saturating_add(Amount::<4>(-1)), Amount::MIN);
+        assert_eq!(Amount::<4>(1).saturating_add(Amount(2)), Amount(3));
+        assert_eq!(Amount::<4>(3).saturating_sub(Amount(2)), Amount(1));
+    }
+
+    #[test]
+    fn cents() {
+        for cents in [0, 1, 12, 1234, -1234, 100000] {
+            let amount = Amount::from_cents(cents);
+            assert_eq!(amount.0, cents * 100);
+            assert_eq!(amount.to_cents(), cents);
+        }
+
+        // Sub-cent fractional parts round to the nearest cent.
+        assert_eq!(Amount::<4>(123400).to_cents(), 1234);
+        assert_eq!(Amount::<4>(123440).to_cents(), 1234);
+        assert_eq!(Amount::<4>(123450).to_cents(), 1235);
+        assert_eq!(Amount::<4>(123449).to_cents(), 1234);
+        assert_eq!(Amount::<4>(123451).to_cents(), 1235);
+        assert_eq!(Amount::<4>(-123450).to_cents(), -1235);
+    }
+
+    #[test]
+    fn milliunits() {
+        for mu in [0, 1, 999, 1234567, -1234567] {
+            let amount = Amount::from_milliunits(mu);
+            assert_eq!(amount.0, mu * 10);
+            assert_eq!(amount.to_milliunits(), mu);
+        }
+
+        // Sub-milliunit values round to the nearest milliunit.
+        assert_eq!(Amount::<4>(12344).to_milliunits(), 1234);
+        assert_eq!(Amount::<4>(12345).to_milliunits(), 1235);
+        assert_eq!(Amount::<4>(12346).to_milliunits(), 1235);
+        assert_eq!(Amount::<4>(-12345).to_milliunits(), -1235);
+    }
+
+    #[test]
+    fn split() {
+        let amount: Amount = 10.0001.into();
+        let parts = amount.split(3).unwrap();
+        assert_eq!(parts, vec![3.3334.into(), 3.3334.into(), 3.3333.into()]);
+        assert_eq!(parts.into_iter().sum::<Amount>(), amount);
+
+        let amount: Amount = (-1.0).into();
+        let parts = amount.split(3).unwrap();
+        assert_eq!(
+            parts,
+            vec![(-0.3334).into(), (-0.3333).into(), (-0.3333).into()]
+        );
+        assert_eq!(parts.into_iter().sum::<Amount>(), amount);
+
+        assert_eq!(Amount::<4>(5).split(1), Ok(vec![Amount(5)]));
+        assert_eq!(Amount::<4>(5).split(0), Err(SplitError::ZeroParts));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Amount::<4>(12345678).to_string(), "1234.5678");
+        assert_eq!(Amount::<4>(10000).to_string(), "1.0000");
+        assert_eq!(Amount::<4>(1).to_string(), "0.0001");
+        assert_eq!(Amount::<4>(0).to_string(), "0.0000");
+        assert_eq!(Amount::<4>(-15000).to_string(), "-1.5000");
+    }
+
+    #[test]
+    fn precision_conversion() {
+        let amount = Amount::<4>(12345678);
+        assert_eq!(
+            amount.checked_to_precision::<8>(),
+            Some(Amount(123456780000))
+        );
+        assert_eq!(amount.checked_to_precision::<2>(), None);
+        assert_eq!(amount.to_precision_lossy::<2>(), Amount(123457));
+        assert_eq!(Amount::<4>(-15000).to_precision_lossy::<2>(), Amount(-150));
+        assert_eq!(Amount::<4>(-15050).to_precision_lossy::<2>(), Amount(-151));
+        assert_eq!(
+            Amount::<4>(12340000).checked_to_precision::<2>(),
+            Some(Amount(123400))
+        );
+        assert_eq!(Amount::<4>(i64::MAX).checked_to_precision::<8>(), None);
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("1234.5678".parse(), Ok(Amount::<4>(12345678)));
+        assert_eq!("-1.5".parse(), Ok(Amount::<4>(-15000)));
+        assert_eq!(".5".parse(), Ok(Amount::<4>(5000)));
+        assert_eq!("7".parse(), Ok(Amount::<8>(700000000)));
+
+        // Extra decimal places round half away from zero.
+        assert_eq!("12.3449".parse(), Ok(Amount::<2>(1234)));
+        assert_eq!("12.3450".parse(), Ok(Amount::<2>(1235)));
+        assert_eq!("12.3456".parse(), Ok(Amount::<2>(1235)));
+        assert_eq!("-12.3450".parse(), Ok(Amount::<2>(-1235)));
+
+        assert_eq!("".parse::<Amount>(), Err(ParseAmountError::Invalid));
+        assert_eq!("1.2.3".parse::<Amount>(), Err(ParseAmountError::Invalid));
+        assert_eq!("abc".parse::<Amount>(), Err(ParseAmountError::Invalid));
+        assert_eq!(
+            "9999999999999999".parse::<Amount>(),
+            Err(ParseAmountError::Overflow)
+        );
+    }
+
+    #[test]
+    fn display_string() {
+        let amount = Amount::<4>(100000000);
+        assert_eq!(amount.to_display_string(true), "10,000.0000");
+        assert_eq!(amount.to_display_string(false), "10000.0000");
+        assert_eq!(Amount::<4>(12345678).to_display_string(true), "1,234.5678");
+        assert_eq!(
+            Amount::<4>(-1234567890000).to_display_string(true),
+            "-123,456,789.0000"
+        );
+        assert_eq!(Amount::<4>(9990000).to_display_string(true), "999.0000");
+        assert_eq!(Amount::<0>(1000).to_display_string(true), "1,000");
+    }
+
+    #[test]
+    fn from_ratio() {
+        let third = Amount::from_ratio(1, 3).unwrap();
+        let two_thirds = Amount::from_ratio(2, 3).unwrap();
+        assert_eq!(third.to_string(), "0.3333");
+        assert_eq!(two_thirds.to_string(), "0.6667");
+        assert_eq!(third + two_thirds, Amount::<4>(10000));
+        // The known loss from rounding each share.
+        assert_eq!(third + third + third, Amount::<4>(9999));
+
+        assert_eq!(Amount::from_ratio(-2, 3), Ok(Amount::<4>(-6667)));
+        assert_eq!(Amount::from_ratio(2, -3), Ok(Amount::<4>(-6667)));
+        assert_eq!(Amount::from_ratio(-1, -8), Ok(Amount::<4>(1250)));
+        assert_eq!(Amount::from_ratio(1, 20_000), Ok(Amount::<4>(1)));
+        assert_eq!(Amount::from_ratio(7, 1), Ok(Amount::<4>(70000)));
+
+        assert_eq!(Amount::from_ratio(1, 0), Err(RatioError::DivisionByZero));
+        assert_eq!(Amount::from_ratio(i64::MAX, 1), Err(RatioError::Overflow));
+    }
+
+    #[test]
+    fn percentage() {
+        assert_eq!(Amount::<4>(2000000).percentage(1.5), Amount::<4>(30000));
+        assert_eq!(Amount::<4>(10000).percentage(0.1), Amount::<4>(10));
+        assert_eq!(Amount::<4>(10000).percentage(0.01), Amount::<4>(1));
+        assert_eq!(Amount::<4>(10000).percentage(0.005), Amount::<4>(1));
+        assert_eq!(Amount::<4>(10000).percentage(0.004), Amount::<4>(0));
+        assert_eq!(
+            Amount::<4>(12345678).percentage(100.0),
+            Amount::<4>(12345678)
+        );
+        assert_eq!(Amount::<4>(-10000).percentage(50.0), Amount::<4>(-5000));
+
+        // Out of range percentages are clamped.
+        assert_eq!(Amount::<4>(10000).percentage(150.0), Amount::<4>(10000));
+        assert_eq!(Amount::<4>(10000).percentage(-5.0), Amount::<4>(0));
+    }
+
+    #[test]
+    fn display_precision() {
+        assert_eq!(Amount::<2>(1235).to_string(), "12.35");
+        assert_eq!(Amount::<8>(1).to_string(), "0.00000001");
+    }
+
     #[test]
     fn deserialize() {
         let data = "1234.5678";
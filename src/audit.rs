@@ -0,0 +1,121 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{bank::DisputeResolution, error::BankError, transaction::Transaction};
+
+/// The outcome of a transaction recorded in the audit trail.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditOutcome {
+    /// The transaction was applied.
+    Ok,
+    /// The transaction could not be applied.
+    Err(BankError),
+    /// The transaction was only simulated through `Bank::process_transaction_dry_run`, and nothing
+    /// was applied.
+    DryRun { would_succeed: bool },
+    /// The transaction could not be applied, as read back by `Bank::import_audit_trail_csv`. Only
+    /// the error message is archived.
+    ArchivedErr(String),
+}
+
+impl AuditOutcome {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, AuditOutcome::Ok)
+    }
+
+    pub fn is_err(&self) -> bool {
+        matches!(self, AuditOutcome::Err(_) | AuditOutcome::ArchivedErr(_))
+    }
+}
+
+impl From<Result<(), BankError>> for AuditOutcome {
+    fn from(result: Result<(), BankError>) -> Self {
+        match result {
+            Ok(()) => AuditOutcome::Ok,
+            Err(err) => AuditOutcome::Err(err),
+        }
+    }
+}
+
+/// A single transaction submitted to a `Bank`, along with the outcome of processing it.
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    transaction: Transaction,
+    outcome: AuditOutcome,
+
+    // Why the dispute was closed, for entries from `Bank::close_dispute_with_reason`.
+    resolution: Option<DisputeResolution>,
+
+    // Key-value pairs attached by `Bank::process_transaction_with_metadata`, or by the bank itself,
+    // see `bank::ORIGIN_METADATA_KEY`.
+    metadata: HashMap<String, String>,
+
+    // When the entry was recorded, relative to the creation of the bank.
+    timestamp: Duration,
+}
+
+impl AuditEntry {
+    pub(crate) fn new(transaction: Transaction, outcome: Result<(), BankError>) -> Self {
+        Self::with_outcome(transaction, outcome.into())
+    }
+
+    pub(crate) fn dry_run(transaction: Transaction, would_succeed: bool) -> Self {
+        Self::with_outcome(transaction, AuditOutcome::DryRun { would_succeed })
+    }
+
+    pub(crate) fn with_outcome(transaction: Transaction, outcome: AuditOutcome) -> Self {
+        Self {
+            transaction,
+            outcome,
+            resolution: None,
+            metadata: HashMap::new(),
+            timestamp: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn with_resolution(mut self, resolution: DisputeResolution) -> Self {
+        self.resolution = Some(resolution);
+        self
+    }
+
+    pub(crate) fn with_metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub(crate) fn with_metadata_entry(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub(crate) fn at(mut self, timestamp: Duration) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    pub fn outcome(&self) -> &AuditOutcome {
+        &self.outcome
+    }
+
+    /// Whether the transaction was only simulated, see `AuditOutcome::DryRun`.
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self.outcome, AuditOutcome::DryRun { .. })
+    }
+
+    pub fn resolution(&self) -> Option<DisputeResolution> {
+        self.resolution
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// How long after the creation of the bank the entry was recorded, according to
+    /// `BankConfig::clock`.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+}
@@ -1,8 +1,22 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque},
+    error::Error,
+    io::{BufReader, Read, Write},
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+use serde::{de::IntoDeserializer, Deserialize};
 
 use crate::{
     account::Account,
     amount::Amount,
+    audit::{AuditEntry, AuditOutcome},
+    config::{BankConfig, PeriodConfig},
+    error::{AccountError, BankError, ConsistencyError, ImportError, IntegrityError},
+    events::{EventHandlers, TransactionEventHandler},
     transaction::{Kind, Transaction},
 };
 
@@ -13,31 +27,713 @@ pub type TransactionID = u32;
 /// needed in the future, and any ongoing disputes that might need to be resolved. All logic for
 /// how the bank operates is applied at this level, the underlying account has no knowledge of how
 /// transactions get processed.
+#[derive(Clone)]
 pub struct Bank {
     // Current state of all accounts
     accounts: HashMap<AccountID, Account>,
 
-    // History of all transactions which are `Kind::Deposit` which might be eventually disputed.
+    // History of all transactions which are `Kind::Deposit` which might be eventually disputed, or
+    // `Kind::Withdrawal` which might be eventually reversed.
     transactions: HashMap<TransactionID, Transaction>,
 
+    // Ids of the recorded transactions of each client, in the order they were recorded. Only
+    // maintained if `BankConfig::enable_client_index` is set.
+    client_transactions: HashMap<AccountID, Vec<TransactionID>>,
+
+    // Every transaction applied to each account, in the order they were applied.
+    statements: HashMap<AccountID, Vec<StatementEntry>>,
+
     // Current ongoing disputes.
     disputes: HashMap<TransactionID, Transaction>,
+
+    // When each of the current ongoing disputes was opened.
+    dispute_opened_at: HashMap<TransactionID, Instant>,
+
+    // Transactions whose dispute has been resolved, releasing the held funds.
+    resolved: HashSet<TransactionID>,
+
+    // Transactions which have been undone by a `Kind::AdminReversal`.
+    reversals: HashSet<TransactionID>,
+
+    // Clients and amounts of the most recently applied transactions, newest last, bounded by
+    // `BankConfig::recent_transactions`. Transactions without an amount are recorded as zero.
+    recent: VecDeque<(AccountID, Amount)>,
+
+    // Whether each of the most recently submitted transactions failed, newest last, bounded by
+    // `BankConfig::recent_transactions`.
+    errors_in_window: VecDeque<bool>,
+
+    // Kinds of all transactions submitted so far, if `BankConfig::track_kinds_seen` is set.
+    kinds_seen: HashSet<Kind>,
+
+    // Every processed transaction and its outcome, if `BankConfig::record_audit_trail` is set.
+    audit_trail: Vec<AuditEntry>,
+
+    // When the bank was created, according to `BankConfig::clock`. Audit trail timestamps are
+    // relative to this.
+    created_at: Instant,
+
+    // Keys of transactions applied through `process_transaction_with_idempotency`, bounded by
+    // `BankConfig::max_idempotency_cache`.
+    idempotency_keys: IdempotencyCache,
+
+    // Net funds moved into the bank by deposits, withdrawals, reversals, and chargebacks, checked
+    // by `reconcile`. Wraps on overflow, since intermediate values may overflow even when the
+    // account balances do not.
+    net_flow: Amount,
+
+    // Next candidate for `generate_transaction_id`. Generated ids count down from the top of the
+    // id space to stay clear of the ids assigned by upstream systems.
+    next_generated_id: TransactionID,
+
+    // Every id handed out by `generate_transaction_id`, so they can be told apart from upstream
+    // ids in the same range.
+    generated_ids: HashSet<TransactionID>,
+
+    // Handlers notified of every successfully applied operation, see `subscribe`.
+    handlers: EventHandlers,
+
+    config: BankConfig,
+}
+
+/// The outcome of `Bank::process_all`.
+#[derive(Debug, Default, PartialEq)]
+pub struct ProcessResult {
+    pub succeeded: u32,
+    pub failed: Vec<(TransactionID, BankError)>,
+    // Items skipped by `Bank::process_from_iterator` because they could not be parsed.
+    pub parse_errors: u32,
+}
+
+/// The outcome of `Bank::force_resolve_all_disputes`, with ids in ascending order.
+#[derive(Debug, Default, PartialEq)]
+pub struct ForceResolveResult {
+    pub resolved: Vec<TransactionID>,
+    // Disputes which could not be resolved, e.g. because their account no longer exists. They
+    // are left open.
+    pub skipped: Vec<TransactionID>,
+}
+
+/// The outcome of `Bank::batch_dispute`, with ids in the order they were given.
+#[derive(Debug, Default, PartialEq)]
+pub struct BatchDisputeResult {
+    pub succeeded: Vec<TransactionID>,
+    pub failed: Vec<(TransactionID, BankError)>,
+}
+
+/// The outcome of `Bank::batch_resolve`.
+pub type BatchResolveResult = BatchDisputeResult;
+
+/// The outcome of `Bank::batch_chargeback`.
+pub type BatchChargebackResult = BatchDisputeResult;
+
+/// Aggregates over a range of accounts, see `Bank::summarize_by_client_range`.
+#[derive(Debug, Default, PartialEq)]
+pub struct RangeSummary {
+    pub account_count: usize,
+    pub total_assets: Amount,
+    pub total_held: Amount,
+    pub locked_count: usize,
+}
+
+/// Recorded transactions at or above a reporting threshold, see `Bank::compliance_report`.
+#[derive(Debug, Default, PartialEq)]
+pub struct ComplianceReport {
+    pub threshold: Amount,
+
+    // As `(tx_id, client, amount)`, sorted by transaction id.
+    pub transactions: Vec<(TransactionID, AccountID, Amount)>,
+}
+
+/// The outcome of `Bank::process_end_of_period`. All ids are sorted in ascending order.
+#[derive(Debug, Default, PartialEq)]
+pub struct PeriodReport {
+    // Sum of all maintenance fees charged.
+    pub fees_collected: Amount,
+
+    // Sum of all interest credited.
+    pub interest_paid: Amount,
+
+    // Disputes which were resolved for being too old.
+    pub expired_disputes: Vec<TransactionID>,
+
+    // Accounts which were flagged for review.
+    pub flagged_accounts: Vec<AccountID>,
+
+    // Operations which could not be applied, with the account they were applied to.
+    pub failed: Vec<(AccountID, BankError)>,
+}
+
+/// The outcome of a bulk operation such as `Bank::apply_bulk_credit`.
+#[derive(Debug, Default, PartialEq)]
+pub struct BulkResult {
+    pub succeeded: u32,
+    pub failed: Vec<(AccountID, BankError)>,
+
+    // Sum of all successfully applied credits or debits.
+    pub total_credited: Amount,
+    pub total_debited: Amount,
+}
+
+/// The predicted effect of a transaction on its client's account, see `Bank::simulate_transaction`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationResult {
+    pub would_succeed: bool,
+    pub new_available: Amount,
+    pub new_held: Amount,
+    pub new_total: Amount,
+}
+
+/// The state of a single account, see `Bank::account_summary`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountSummary {
+    pub client: AccountID,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub is_locked: bool,
+    pub is_frozen: bool,
+    pub open_disputes: usize,
+    // Transactions successfully applied to the account.
+    pub total_transactions: usize,
+    // Chargebacks in the account's chargeback history.
+    pub chargebacks: usize,
+    pub fees_paid: Amount,
+}
+
+/// The audit trail metadata key set on entries of transactions initiated by the bank itself: to
+/// `fee` for fees, and to `bank` for anything else, such as initial deposit bonuses.
+pub const ORIGIN_METADATA_KEY: &str = "origin";
+
+/// The audit trail metadata key set to `true` on entries of transactions whose id was generated by
+/// the bank itself, e.g. for fees and bulk credits.
+pub const GENERATED_ID_METADATA_KEY: &str = "generated_id";
+
+// Who initiated an operation, which decides the checks and bookkeeping it goes through.
+#[derive(Clone, Copy, PartialEq)]
+enum Origin {
+    // Submitted for a client. Subject to the freeze and velocity checks, and counted towards the
+    // client's recent activity and transaction count.
+    Client,
+    // Initiated by the bank itself, e.g. an initial deposit bonus. Skips the freeze and velocity
+    // checks, and is not counted towards the client's activity.
+    Bank,
+    // A fee charged by the bank. Same as `Bank`, and also added to the account's fee total.
+    Fee,
+}
+
+impl Origin {
+    // The value of `ORIGIN_METADATA_KEY` in audit trail entries, if any.
+    fn metadata_value(self) -> Option<&'static str> {
+        match self {
+            Origin::Client => None,
+            Origin::Bank => Some("bank"),
+            Origin::Fee => Some("fee"),
+        }
+    }
+
+    fn from_metadata(metadata: &HashMap<String, String>) -> Self {
+        match metadata.get(ORIGIN_METADATA_KEY).map(String::as_str) {
+            Some("bank") => Origin::Bank,
+            Some("fee") => Origin::Fee,
+            _ => Origin::Client,
+        }
+    }
+}
+
+// Idempotency keys, evicting the least recently used once full. Cloning is implemented by hand
+// since cloning an unbounded `LruCache` tries to allocate its full capacity.
+struct IdempotencyCache {
+    keys: LruCache<String, ()>,
+    max: Option<usize>,
+}
+
+impl IdempotencyCache {
+    fn new(max: Option<usize>) -> Self {
+        let keys = match max {
+            Some(max) => LruCache::new(NonZeroUsize::new(max).unwrap_or(NonZeroUsize::MIN)),
+            None => LruCache::unbounded(),
+        };
+        Self { keys, max }
+    }
+}
+
+impl Clone for IdempotencyCache {
+    fn clone(&self) -> Self {
+        let mut cache = Self::new(self.max);
+        // Insert the least recently used first, to keep the same eviction order.
+        for (key, _) in self.keys.iter().rev() {
+            cache.keys.put(key.clone(), ());
+        }
+        cache
+    }
+}
+
+/// The outcome of `Bank::process_transaction_with_idempotency`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdempotencyOutcome {
+    /// The transaction was applied.
+    Applied,
+    /// A transaction with the same idempotency key was already applied, so nothing was done.
+    AlreadySeen,
+}
+
+/// The current state of a transaction, see `Bank::describe_transaction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransactionStatus {
+    /// The transaction was applied and is not disputed.
+    Settled,
+    /// The transaction is currently disputed.
+    InDispute,
+    /// The transaction was disputed, and the dispute was resolved.
+    Resolved,
+    /// The transaction was disputed, and charged back.
+    ChargedBack,
+    /// The transaction was submitted, but is not recorded, e.g. because it failed or is not a
+    /// deposit or withdrawal. Only known through the audit trail.
+    NotFound,
+}
+
+/// A transaction applied to an account, see `Bank::account_statement`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementEntry {
+    pub tx_id: TransactionID,
+    pub kind: Kind,
+    pub amount: Option<Amount>,
+    // Total funds of the account after the transaction was applied.
+    pub balance_after: Amount,
+    pub memo: Option<String>,
+}
+
+/// A description of a transaction and its current state, for customer support.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionDescription {
+    pub id: TransactionID,
+    pub client: AccountID,
+    pub kind: Kind,
+    pub amount: Option<Amount>,
+    pub status: TransactionStatus,
+    pub memo: Option<String>,
+}
+
+/// Why a dispute was closed, see `Bank::close_dispute_with_reason`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisputeResolution {
+    MerchantAccepted,
+    BankDecision,
+    AutoExpired,
+    CustomerWithdrawn,
+}
+
+/// Reasons an account may require human review, see `Bank::accounts_needing_review`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReviewFlag {
+    Locked,
+    ExcessiveDisputes(u32),
+    NegativeBalance,
+    LargeHeld(Amount),
+}
+
+/// The difference between two `Bank` states, as computed by `Bank::diff`. All ids are sorted in
+/// ascending order so that the result is deterministic.
+#[derive(Debug, Default, PartialEq)]
+pub struct BankDiff {
+    // Accounts present in the other bank but not in this one.
+    pub new_accounts: Vec<AccountID>,
+
+    // Accounts present in this bank but not in the other one.
+    pub removed_accounts: Vec<AccountID>,
+
+    // Accounts present in both banks whose state differs, as `(id, before, after)`.
+    pub modified_accounts: Vec<(AccountID, Account, Account)>,
+
+    // Transactions recorded in the other bank but not in this one.
+    pub new_transactions: Vec<TransactionID>,
+
+    // Disputes open in this bank that are no longer open in the other one.
+    pub resolved_disputes: Vec<TransactionID>,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bank {
     pub fn new() -> Self {
+        Self::with_config(BankConfig::default())
+    }
+
+    pub fn with_config(config: BankConfig) -> Self {
         Self {
             accounts: HashMap::new(),
             transactions: HashMap::new(),
+            client_transactions: HashMap::new(),
+            statements: HashMap::new(),
             disputes: HashMap::new(),
+            dispute_opened_at: HashMap::new(),
+            resolved: HashSet::new(),
+            reversals: HashSet::new(),
+            recent: VecDeque::new(),
+            errors_in_window: VecDeque::new(),
+            kinds_seen: HashSet::new(),
+            audit_trail: Vec::new(),
+            created_at: config.clock.now(),
+            idempotency_keys: IdempotencyCache::new(config.max_idempotency_cache),
+            net_flow: Amount::new(),
+            next_generated_id: TransactionID::MAX,
+            generated_ids: HashSet::new(),
+            handlers: EventHandlers::default(),
+            config,
+        }
+    }
+
+    /// Create a bank with room for `accounts` accounts and `transactions` recorded transactions and
+    /// open disputes without reallocating.
+    pub fn with_capacity(accounts: usize, transactions: usize) -> Self {
+        Self {
+            accounts: HashMap::with_capacity(accounts),
+            transactions: HashMap::with_capacity(transactions),
+            disputes: HashMap::with_capacity(transactions),
+            dispute_opened_at: HashMap::with_capacity(transactions),
+            ..Self::new()
+        }
+    }
+
+    /// Forget pairs of a deposit and a later withdrawal by the same client for the same amount,
+    /// since they cancel out. Transactions which are disputed or reversed are kept, and "later" is
+    /// judged by transaction id. Compacted deposits can no longer be disputed. Balances are not
+    /// affected. Returns the number of pairs removed.
+    pub fn compact_transactions(&mut self) -> usize {
+        let mut ids: Vec<TransactionID> = self
+            .transactions
+            .keys()
+            .filter(|id| !self.disputes.contains_key(id) && !self.reversals.contains(id))
+            .copied()
+            .collect();
+        ids.sort_unstable();
+
+        // Deposits which have not been paired yet, by client and amount.
+        let mut deposits: HashMap<(AccountID, Amount), Vec<TransactionID>> = HashMap::new();
+        let mut removed = Vec::new();
+        for id in ids {
+            let transaction = &self.transactions[&id];
+            // unwrap is safe because we only would have inserted into `self.transactions` if there
+            // was a valid amount.
+            let key = (transaction.client(), transaction.amount().unwrap());
+            match transaction.kind() {
+                Kind::Deposit => deposits.entry(key).or_default().push(id),
+                Kind::Withdrawal => {
+                    if let Some(deposit) = deposits.get_mut(&key).and_then(Vec::pop) {
+                        removed.push((key.0, deposit, id));
+                    }
+                }
+                _ => unreachable!("only deposits and withdrawals are recorded"),
+            }
+        }
+
+        for (client, deposit, withdrawal) in &removed {
+            self.transactions.remove(deposit);
+            self.transactions.remove(withdrawal);
+            if let Some(ids) = self.client_transactions.get_mut(client) {
+                ids.retain(|id| id != deposit && id != withdrawal);
+            }
+        }
+        removed.len()
+    }
+
+    /// Reserve room for at least `additional_accounts` more accounts and `additional_transactions`
+    /// more recorded transactions and open disputes, e.g. before a large import.
+    pub fn reserve(&mut self, additional_accounts: usize, additional_transactions: usize) {
+        self.accounts.reserve(additional_accounts);
+        self.transactions.reserve(additional_transactions);
+        self.disputes.reserve(additional_transactions);
+        self.dispute_opened_at.reserve(additional_transactions);
+    }
+
+    /// Create a bank which records at most `limit` deposits and withdrawals, rejecting further ones
+    /// with `BankError::StorageFull`.
+    pub fn with_transaction_limit(limit: usize) -> Self {
+        Self::with_config(BankConfig {
+            max_transactions: Some(limit),
+            ..BankConfig::default()
+        })
+    }
+
+    /// Release unused capacity of the internal maps, e.g. after a burst of activity has been
+    /// cleaned up.
+    pub fn shrink_to_fit(&mut self) {
+        self.accounts.shrink_to_fit();
+        self.transactions.shrink_to_fit();
+        self.disputes.shrink_to_fit();
+        self.dispute_opened_at.shrink_to_fit();
+    }
+
+    /// Create a bank from pre-existing accounts, e.g. when restoring state. The bank starts with no
+    /// transaction history and no open disputes.
+    pub fn with_accounts(accounts: impl IntoIterator<Item = (AccountID, Account)>) -> Self {
+        Self {
+            accounts: accounts.into_iter().collect(),
+            ..Self::new()
+        }
+    }
+
+    /// Create an empty sub-account of `parent`, assigned the lowest unused `AccountID`.
+    pub fn create_sub_account(&mut self, parent: AccountID) -> Result<AccountID, BankError> {
+        if !self.accounts.contains_key(&parent) {
+            return Err(BankError::AccountNotFound(parent));
+        }
+
+        let id = (0..=AccountID::MAX)
+            .find(|id| !self.accounts.contains_key(id))
+            .ok_or(BankError::AccountIdsExhausted)?;
+        self.accounts.insert(id, Account::with_parent(parent));
+        Ok(id)
+    }
+
+    /// Sum of the total funds of `parent` and all of its direct sub-accounts.
+    pub fn get_group_balance(&self, parent: AccountID) -> Amount {
+        self.accounts
+            .iter()
+            .filter(|(id, account)| **id == parent || account.parent_id() == Some(parent))
+            .map(|(_, account)| account.total())
+            .fold(Amount::new(), Amount::saturating_add)
+    }
+
+    /// All account ids grouped by their parent account, in ascending order. Accounts which are not
+    /// a sub-account of any other account are grouped under `None`.
+    pub fn accounts_by_group(&self) -> HashMap<Option<AccountID>, Vec<AccountID>> {
+        let mut groups: HashMap<Option<AccountID>, Vec<AccountID>> = HashMap::new();
+        for (id, account) in &self.accounts {
+            groups.entry(account.parent_id()).or_default().push(*id);
+        }
+        for ids in groups.values_mut() {
+            ids.sort_unstable();
+        }
+        groups
+    }
+
+    /// Consolidate `source` into `dest`: the available funds of `source` are credited to `dest`,
+    /// its transactions, sub-accounts, and recent activity are re-pointed to `dest`, its statement
+    /// is appended to the statement of `dest`, and `source` is removed. The
+    /// merge is refused if `source` has open disputes, since its held funds can not be moved
+    /// without settling them first. Nothing is modified if an error is returned.
+    pub fn merge_accounts(&mut self, source: AccountID, dest: AccountID) -> Result<(), BankError> {
+        if source == dest {
+            return Err(BankError::SameAccount(source));
+        }
+        let available = self
+            .accounts
+            .get(&source)
+            .ok_or(BankError::AccountNotFound(source))?
+            .available();
+        if self.has_open_disputes_for_client(source) {
+            return Err(BankError::OpenDisputes(source));
+        }
+        self.check_not_frozen(source)?;
+        self.check_not_frozen(dest)?;
+
+        let dest_account = self
+            .accounts
+            .get_mut(&dest)
+            .ok_or(BankError::AccountNotFound(dest))?;
+        // An overdrawn source passes its debt on to `dest`.
+        if available.is_negative() {
+            dest_account.try_debit(-available)?;
+        } else {
+            dest_account.try_credit(available)?;
         }
+        self.accounts.remove(&source);
+
+        for transaction in self.transactions.values_mut() {
+            if transaction.client() == source {
+                transaction.set_client(dest);
+            }
+        }
+        if let Some(statement) = self.statements.remove(&source) {
+            self.statements.entry(dest).or_default().extend(statement);
+        }
+        for (client, _) in self.recent.iter_mut() {
+            if *client == source {
+                *client = dest;
+            }
+        }
+        if let Some(ids) = self.client_transactions.remove(&source) {
+            self.client_transactions
+                .entry(dest)
+                .or_default()
+                .extend(ids);
+        }
+        for account in self.accounts.values_mut() {
+            if account.parent_id() == Some(source) {
+                account.set_parent_id(Some(dest));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Admin operation to move the account of `old_id` to `new_id`, e.g. during a migration. Its
+    /// recorded transactions, open disputes, and sub-accounts follow it. The audit trail is left
+    /// as it was recorded.
+    pub fn rekey_account(&mut self, old_id: AccountID, new_id: AccountID) -> Result<(), BankError> {
+        if !self.accounts.contains_key(&old_id) {
+            return Err(BankError::AccountNotFound(old_id));
+        }
+        if self.accounts.contains_key(&new_id) {
+            return Err(BankError::DuplicateAccountId(new_id));
+        }
+
+        // unwrap is safe since we checked the account exists above.
+        let account = self.accounts.remove(&old_id).unwrap();
+        self.accounts.insert(new_id, account);
+        for transaction in self
+            .transactions
+            .values_mut()
+            .chain(self.disputes.values_mut())
+        {
+            if transaction.client() == old_id {
+                transaction.set_client(new_id);
+            }
+        }
+        if let Some(statement) = self.statements.remove(&old_id) {
+            self.statements.insert(new_id, statement);
+        }
+        if let Some(ids) = self.client_transactions.remove(&old_id) {
+            self.client_transactions.insert(new_id, ids);
+        }
+        for (client, _) in self.recent.iter_mut() {
+            if *client == old_id {
+                *client = new_id;
+            }
+        }
+        for account in self.accounts.values_mut() {
+            if account.parent_id() == Some(old_id) {
+                account.set_parent_id(Some(new_id));
+            }
+        }
+
+        Ok(())
     }
 
     // Public exposure. Ensure to report valid floating point values.
     pub fn accounts_iter(&self) -> impl Iterator<Item = (u16, Amount, Amount, Amount, bool)> + '_ {
         self.accounts.iter().map(|(id, account)| {
+            let (available, held, total, locked) = account.as_balance_tuple();
+            (*id, available, held, total, locked)
+        })
+    }
+
+    /// Same as `accounts_iter`, but yields each account itself rather than its balances.
+    pub fn accounts_iter_with_account(&self) -> impl Iterator<Item = (AccountID, &Account)> + '_ {
+        self.accounts.iter().map(|(id, account)| (*id, account))
+    }
+
+    /// Mutable access to every account, for administrative bulk updates. Changes made this way are
+    /// not recorded as transactions, so they are not reflected by `reconcile`.
+    pub fn accounts_iter_mut(&mut self) -> impl Iterator<Item = (AccountID, &mut Account)> + '_ {
+        self.accounts.iter_mut().map(|(id, account)| (*id, account))
+    }
+
+    /// Remove every account for which `predicate` returns false, along with its transactions,
+    /// disputes, and statement. The funds of removed accounts leave the bank without a transaction,
+    /// so they are not reflected by `reconcile`. Sub-accounts of removed accounts are detached from
+    /// them.
+    pub fn retain_accounts<F>(&mut self, mut predicate: F)
+    where
+        F: FnMut(AccountID, &Account) -> bool,
+    {
+        let mut removed = HashSet::new();
+        self.accounts.retain(|id, account| {
+            let keep = predicate(*id, account);
+            if !keep {
+                removed.insert(*id);
+            }
+            keep
+        });
+        if removed.is_empty() {
+            return;
+        }
+
+        let mut removed_transactions = HashSet::new();
+        self.transactions.retain(|id, t| {
+            let keep = !removed.contains(&t.client());
+            if !keep {
+                removed_transactions.insert(*id);
+            }
+            keep
+        });
+        self.disputes.retain(|_, t| !removed.contains(&t.client()));
+        let disputes = &self.disputes;
+        self.dispute_opened_at
+            .retain(|id, _| disputes.contains_key(id));
+        self.resolved
+            .retain(|id| !removed_transactions.contains(id));
+        self.reversals
+            .retain(|id| !removed_transactions.contains(id));
+        self.client_transactions
+            .retain(|id, _| !removed.contains(id));
+        self.statements.retain(|id, _| !removed.contains(id));
+        for account in self.accounts.values_mut() {
+            if account
+                .parent_id()
+                .is_some_and(|parent| removed.contains(&parent))
+            {
+                account.set_parent_id(None);
+            }
+        }
+    }
+
+    /// Remove the account of `client` along with all its data, like `retain_accounts`, returning
+    /// the account and its recorded transactions in ascending id order so they can be migrated
+    /// elsewhere. Open disputes of the client are dropped, their transactions are among the
+    /// returned ones. Sub-accounts of the client are detached from it. Returns `None` if the
+    /// client has no account.
+    pub fn pop_account(&mut self, client: AccountID) -> Option<(Account, Vec<Transaction>)> {
+        let account = self.accounts.remove(&client)?;
+
+        let ids: Vec<TransactionID> = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.client() == client)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut transactions = Vec::with_capacity(ids.len());
+        for id in ids {
+            self.resolved.remove(&id);
+            self.reversals.remove(&id);
+            transactions.extend(self.transactions.remove(&id));
+        }
+        transactions.sort_unstable_by_key(Transaction::id);
+
+        self.disputes.retain(|_, t| t.client() != client);
+        let disputes = &self.disputes;
+        self.dispute_opened_at
+            .retain(|id, _| disputes.contains_key(id));
+        self.client_transactions.remove(&client);
+        self.statements.remove(&client);
+        for account in self.accounts.values_mut() {
+            if account.parent_id() == Some(client) {
+                account.set_parent_id(None);
+            }
+        }
+
+        Some((account, transactions))
+    }
+
+    /// Same as `accounts_iter`, but yields accounts in ascending `AccountID` order. This allocates
+    /// a `Vec` of all account ids to sort them up front.
+    pub fn accounts_iter_sorted_by_id(
+        &self,
+    ) -> impl Iterator<Item = (AccountID, Amount, Amount, Amount, bool)> + '_ {
+        let mut ids: Vec<AccountID> = self.accounts.keys().copied().collect();
+        ids.sort_unstable();
+
+        ids.into_iter().map(|id| {
+            let account = &self.accounts[&id];
             (
-                *id,
+                id,
                 account.available(),
                 account.held(),
                 account.total(),
@@ -46,117 +742,5672 @@ impl Bank {
         })
     }
 
-    pub fn process_transaction(&mut self, transaction: Transaction) {
-        match transaction.kind() {
-            Kind::Deposit => self.process_deposit(transaction),
-            Kind::Withdrawal => self.process_withdrawl(transaction),
-            Kind::Dispute => self.process_dispute(transaction),
-            Kind::Resolve => self.process_resolve(transaction),
-            Kind::Chargeback => self.process_chargeback(transaction),
-        };
+    /// Ids of all accounts locked by a chargeback, in no particular order.
+    pub fn locked_accounts(&self) -> impl Iterator<Item = AccountID> + '_ {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.is_locked())
+            .map(|(id, _)| *id)
     }
 
-    // TODO: Handle credit missing amounts
-    // TODO: Handle duplicate transactions id's
-    fn process_deposit(&mut self, transaction: Transaction) {
-        if let Some(amount) = transaction.amount() {
-            let account = self
-                .accounts
-                .entry(transaction.client())
-                .or_insert_with(Account::new);
+    /// Ids of all accounts frozen by an administrator, in no particular order.
+    pub fn frozen_accounts(&self) -> impl Iterator<Item = AccountID> + '_ {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.is_frozen())
+            .map(|(id, _)| *id)
+    }
 
-            account.credit(amount);
-            self.transactions.insert(transaction.id(), transaction);
-        }
+    /// Ids of all accounts holding no funds at all, neither available nor held, in no particular
+    /// order. These are candidates for closure or fee exemption.
+    pub fn accounts_with_zero_balance(&self) -> impl Iterator<Item = AccountID> + '_ {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.total().is_zero())
+            .map(|(id, _)| *id)
     }
 
-    // TODO: Handle accounts missing funds for debit
-    // TODO: Handle transaction missing amounts
-    // TODO: Handle debit from non-existant accounts
-    fn process_withdrawl(&mut self, transaction: Transaction) {
-        if let Some(account) = self.accounts.get_mut(&transaction.client()) {
-            if let Some(amount) = transaction.amount() {
-                let _ = account.try_debit(amount);
-            }
+    /// Accounts overdrawn through their account limit, with their (negative) available funds, in
+    /// no particular order.
+    pub fn accounts_with_negative_available(
+        &self,
+    ) -> impl Iterator<Item = (AccountID, Amount)> + '_ {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.available().is_negative())
+            .map(|(id, account)| (*id, account.available()))
+    }
+
+    /// Write the balances of every account as CSV, with the columns
+    /// `client,available,held,total,locked`. Accounts are in no particular order.
+    pub fn export_csv(&self, writer: impl Write) -> std::io::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize(("client", "available", "held", "total", "locked"))?;
+        for row in self.accounts_iter() {
+            writer.serialize(row)?;
         }
+        writer.flush()
+    }
+
+    /// Same as `export_csv`, writing to stdout. Stdout is locked while writing, so the report is
+    /// not interleaved with output from other threads, and flushed afterwards.
+    pub fn export_csv_to_stdout(&self) -> std::io::Result<()> {
+        let stdout = std::io::stdout();
+        self.export_csv(stdout.lock())
+    }
+
+    /// Print a short summary of the bank's state to stderr, as a debugging aid.
+    pub fn print_summary(&self) {
+        // Failing to write a debugging aid to stderr is not worth reporting.
+        let _ = self.write_summary(std::io::stderr().lock());
+    }
+
+    fn write_summary(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "accounts: {}", self.accounts.len())?;
+        writeln!(
+            writer,
+            "locked accounts: {}",
+            self.locked_accounts().count()
+        )?;
+        writeln!(writer, "open disputes: {}", self.disputes.len())?;
+        writeln!(writer, "total assets: {}", self.total_assets())?;
+        writeln!(writer, "total held: {}", self.total_held())?;
+        writeln!(writer, "transactions: {}", self.transactions.len())
     }
 
-    // TODO: Handle multiple disputes at once for the same transaction id
-    // TODO: Handle disputes where values have already been withdrawn or are not available
-    // TODO: Handle disputes where the transaction in dispute is not from the same client
-    fn process_dispute(&mut self, transaction: Transaction) {
-        if let Some(old_transaction) = self.transactions.get(&transaction.id()) {
-            if let Some(account) = self.accounts.get_mut(&old_transaction.client()) {
-                // unwrap is safe because we only would have inserted into `self.transactions` if
-                // there was a valid amount.
-                if let Ok(_) = account.try_dispute(old_transaction.amount().unwrap()) {
-                    self.disputes
-                        .insert(transaction.id(), old_transaction.clone());
+    /// Create a bank from an accounts balance report, as written by `export_csv`. The bank has no
+    /// transaction history or open disputes, so held funds can only be released by an operator,
+    /// e.g. through `force_resolve_all_disputes`.
+    pub fn import_state_from_accounts_csv(reader: impl Read) -> Result<Bank, ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        let mut accounts = HashMap::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| ImportError::MalformedRow {
+                line: e.position().map_or(0, |p| p.line()),
+            })?;
+            let line = record.position().map_or(0, |p| p.line());
+            let malformed = ImportError::MalformedRow { line };
+            let invalid = ImportError::InvalidAmount { line };
+
+            let fields: Vec<_> = record.iter().collect();
+            let (client, available, held, total, locked) = match fields[..] {
+                [client, available, held, total, locked] => {
+                    (client, available, held, total, locked)
                 }
+                _ => return Err(malformed),
+            };
+            let client: AccountID = client.parse().map_err(|_| malformed.clone())?;
+            let locked: bool = locked.parse().map_err(|_| malformed)?;
+            let available: Amount = available.parse().map_err(|_| invalid.clone())?;
+            let held: Amount = held.parse().map_err(|_| invalid.clone())?;
+            let total: Amount = total.parse().map_err(|_| invalid.clone())?;
+            if held.is_negative() || available.checked_add(held) != Some(total) {
+                return Err(invalid);
+            }
+
+            if accounts
+                .insert(client, Account::with_balances(available, held, locked))
+                .is_some()
+            {
+                return Err(ImportError::DuplicateClient(client));
             }
         }
+
+        Ok(Bank::with_accounts(accounts))
     }
 
-    fn process_resolve(&mut self, transaction: Transaction) {
-        if let Some(transaction) = self.disputes.remove(&transaction.id()) {
-            if let Some(account) = self.accounts.get_mut(&transaction.client()) {
-                // unwrap is safe because we only would have inserted into `self.transactions` if
-                // there was a valid amount.
-                let _ = account.try_resolve(transaction.amount().unwrap());
-            }
+    /// Every transaction applied to `client`, oldest first. Empty unless
+    /// `BankConfig::record_statements` is set.
+    pub fn account_statement(&self, client: AccountID) -> &[StatementEntry] {
+        self.statements.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    /// How much the total funds of `client` changed since deposit `tx_id` was applied, based on
+    /// the account statement, see `account_statement`. `None` if the deposit is not on the
+    /// statement.
+    pub fn net_change_since_deposit(
+        &self,
+        client: AccountID,
+        tx_id: TransactionID,
+    ) -> Option<Amount> {
+        let account = self.accounts.get(&client)?;
+        let entry = self
+            .account_statement(client)
+            .iter()
+            .find(|e| e.tx_id == tx_id && e.kind == Kind::Deposit)?;
+        Some(account.total() - entry.balance_after)
+    }
+
+    /// Write the statement of `client` as CSV, with the columns
+    /// `tx_id,kind,amount,balance_after,memo`. Only the header is written unless
+    /// `BankConfig::record_statements` is set, see `account_statement`.
+    pub fn account_statement_csv(
+        &self,
+        client: AccountID,
+        writer: impl Write,
+    ) -> std::io::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize(("tx_id", "kind", "amount", "balance_after", "memo"))?;
+        for entry in self.account_statement(client) {
+            writer.serialize((
+                entry.tx_id,
+                entry.kind,
+                entry.amount,
+                entry.balance_after,
+                &entry.memo,
+            ))?;
         }
+        writer.flush()
     }
 
-    fn process_chargeback(&mut self, transaction: Transaction) {
-        if let Some(transaction) = self.disputes.remove(&transaction.id()) {
-            if let Some(account) = self.accounts.get_mut(&transaction.client()) {
-                // unwrap is safe because we only would have inserted into `self.transactions` if
-                // there was a valid amount.
-                let _ = account.try_chargeback(transaction.amount().unwrap());
-            }
+    /// Write the recorded deposits and withdrawals of `client` as CSV, with the columns
+    /// `type,client,tx,amount`, ordered by transaction id.
+    pub fn export_transactions_csv(
+        &self,
+        client: AccountID,
+        writer: impl Write,
+    ) -> std::io::Result<()> {
+        let mut transactions: Vec<&Transaction> =
+            self.find_transactions_for_client(client).collect();
+        transactions.sort_unstable_by_key(|t| t.id());
+
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize(("type", "client", "tx", "amount"))?;
+        for transaction in transactions {
+            writer.serialize((
+                transaction.kind(),
+                transaction.client(),
+                transaction.id(),
+                transaction.amount(),
+            ))?;
         }
+        writer.flush()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Write every open dispute as CSV with the columns `tx_id,client,amount,opened_at`, sorted by
+    /// transaction id. `amount` is the amount still disputed, and `opened_at` when the dispute was
+    /// opened, in milliseconds since the creation of the bank according to `BankConfig::clock`.
+    pub fn export_disputes_csv(&self, writer: impl Write) -> std::io::Result<()> {
+        let mut disputes: Vec<&Transaction> = self.disputes.values().collect();
+        disputes.sort_unstable_by_key(|t| t.id());
 
-    #[test]
-    fn iterator() {
-        let mut account = Account::new();
-        account.credit(10000.into());
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize(("tx_id", "client", "amount", "opened_at"))?;
+        for dispute in disputes {
+            let opened_at = self
+                .dispute_opened_at
+                .get(&dispute.id())
+                .map(|opened_at| opened_at.saturating_duration_since(self.created_at))
+                .unwrap_or_default();
+            writer.serialize((
+                dispute.id(),
+                dispute.client(),
+                dispute.amount(),
+                opened_at.as_millis(),
+            ))?;
+        }
+        writer.flush()
+    }
 
-        let mut dispute_account = Account::new();
-        dispute_account.credit(10000.into());
-        dispute_account.try_dispute(5000.into()).unwrap();
+    /// Restore the open disputes written by `export_disputes_csv`, e.g. when restoring from a
+    /// snapshot whose transactions and account balances are already in place. The disputes are
+    /// added as they are, without holding any funds. `opened_at` is taken relative to the creation
+    /// of this bank. Nothing is imported if any row is invalid.
+    pub fn import_disputes_from_csv(&mut self, reader: impl Read) -> Result<(), ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
 
-        let mut frozen_account = Account::new();
-        frozen_account.credit(10000.into());
-        frozen_account.try_dispute(5000.into()).unwrap();
-        frozen_account.try_chargeback(5000.into()).unwrap();
+        let mut disputes = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| ImportError::MalformedRow {
+                line: e.position().map_or(0, |p| p.line()),
+            })?;
+            let line = record.position().map_or(0, |p| p.line());
+            let malformed = ImportError::MalformedRow { line };
+            let invalid = ImportError::InvalidAmount { line };
 
-        let mut accounts = HashMap::new();
-        accounts.insert(1, account);
-        accounts.insert(2, dispute_account);
-        accounts.insert(3, frozen_account);
+            let (tx_id, client, amount, opened_at) = match record.iter().collect::<Vec<_>>()[..] {
+                [tx_id, client, amount, opened_at] => (tx_id, client, amount, opened_at),
+                _ => return Err(malformed),
+            };
+            let tx_id: TransactionID = tx_id.parse().map_err(|_| malformed.clone())?;
+            let client: AccountID = client.parse().map_err(|_| malformed.clone())?;
+            let opened_at: u64 = opened_at.parse().map_err(|_| malformed.clone())?;
+            let opened_at = self
+                .created_at
+                .checked_add(Duration::from_millis(opened_at))
+                .ok_or(malformed)?;
+            let amount: Amount = amount.parse().map_err(|_| invalid.clone())?;
 
-        let bank = Bank {
-            accounts,
-            transactions: HashMap::new(),
-            disputes: HashMap::new(),
+            let mut transaction = self
+                .transactions
+                .get(&tx_id)
+                .filter(|t| t.client() == client && t.kind() == Kind::Deposit)
+                .ok_or(ImportError::UnknownTransaction(tx_id))?
+                .clone();
+            if amount.is_negative() || Some(amount) > transaction.amount() {
+                return Err(invalid);
+            }
+            transaction.set_amount(Some(amount));
+            disputes.push((transaction, opened_at));
+        }
+
+        for (transaction, opened_at) in disputes {
+            self.dispute_opened_at.insert(transaction.id(), opened_at);
+            self.disputes.insert(transaction.id(), transaction);
+        }
+        Ok(())
+    }
+
+    pub fn config(&self) -> &BankConfig {
+        &self.config
+    }
+
+    pub fn get_account(&self, client: AccountID) -> Option<&Account> {
+        self.accounts.get(&client)
+    }
+
+    /// The highest id of any recorded transaction or open dispute, or `None` if there are none.
+    /// Ids generated by the bank itself, e.g. for bulk credits, count down from the top of the id
+    /// space and are not considered.
+    pub fn highest_transaction_id_seen(&self) -> Option<TransactionID> {
+        self.all_transaction_ids()
+            .filter(|id| !self.is_generated_id(*id))
+            .max()
+    }
+
+    /// Inclusive ranges `(start, end)` of ids from 1 up to `highest_transaction_id_seen` which are
+    /// not recorded transactions, in ascending order. Useful for protocols whose ids must be
+    /// contiguous. Ids generated by the bank itself are not considered.
+    pub fn transaction_id_gaps(&self) -> Vec<(TransactionID, TransactionID)> {
+        let highest = match self.highest_transaction_id_seen() {
+            Some(highest) => highest,
+            None => return Vec::new(),
         };
+        let mut ids: Vec<TransactionID> = self
+            .transactions
+            .keys()
+            .copied()
+            .filter(|id| !self.is_generated_id(*id))
+            .collect();
+        ids.sort_unstable();
 
-        let mut accounts: Vec<(u16, Amount, Amount, Amount, bool)> = bank.accounts_iter().collect();
-        accounts.sort_by(|x, y| x.0.cmp(&y.0));
+        let mut gaps = Vec::new();
+        let mut previous = 0;
+        for id in ids {
+            if id > previous + 1 {
+                gaps.push((previous + 1, id - 1));
+            }
+            previous = id;
+        }
+        if highest > previous {
+            gaps.push((previous + 1, highest));
+        }
+        gaps
+    }
 
-        assert_eq!(
-            accounts,
-            vec![
-                (1, 1.0.into(), 0.0.into(), 1.0.into(), false),
-                (2, 0.5.into(), 0.5.into(), 1.0.into(), false),
-                (3, 0.5.into(), 0.0.into(), 0.5.into(), true),
-            ]
-        )
+    /// Whether `tx_id` is a recorded transaction or an open dispute.
+    pub fn contains_transaction(&self, tx_id: TransactionID) -> bool {
+        self.transactions.contains_key(&tx_id) || self.disputes.contains_key(&tx_id)
+    }
+
+    pub fn contains_account(&self, client: AccountID) -> bool {
+        self.accounts.contains_key(&client)
+    }
+
+    /// The number of transactions successfully applied to `client` over its lifetime, see
+    /// `Account::transaction_count`.
+    pub fn account_age_in_transactions(&self, client: AccountID) -> Option<usize> {
+        self.accounts.get(&client).map(Account::transaction_count)
+    }
+
+    /// Held funds of every account, i.e. the amount each account has tied up in open disputes.
+    pub fn pending_dispute_amounts_per_account(&self) -> HashMap<AccountID, Amount> {
+        self.accounts
+            .iter()
+            .map(|(id, account)| (*id, account.held()))
+            .collect()
+    }
+
+    /// Describe the transaction `tx_id` and its current state. Transactions which were not recorded
+    /// are described from their latest entry in the audit trail, if there is one.
+    pub fn describe_transaction(&self, tx_id: TransactionID) -> Option<TransactionDescription> {
+        let (transaction, status) = match self.transactions.get(&tx_id) {
+            Some(transaction) => {
+                let charged_back = self
+                    .accounts
+                    .get(&transaction.client())
+                    .is_some_and(|a| a.chargeback_history().iter().any(|(id, _)| *id == tx_id));
+                let status = if self.disputes.contains_key(&tx_id) {
+                    TransactionStatus::InDispute
+                } else if charged_back {
+                    TransactionStatus::ChargedBack
+                } else if self.resolved.contains(&tx_id) {
+                    TransactionStatus::Resolved
+                } else {
+                    TransactionStatus::Settled
+                };
+                (transaction, status)
+            }
+            None => {
+                let entry = self
+                    .audit_trail
+                    .iter()
+                    .rev()
+                    .find(|e| e.transaction().id() == tx_id)?;
+                (entry.transaction(), TransactionStatus::NotFound)
+            }
+        };
+
+        Some(TransactionDescription {
+            id: tx_id,
+            client: transaction.client(),
+            kind: transaction.kind(),
+            amount: transaction.amount(),
+            status,
+            memo: transaction.memo().map(str::to_string),
+        })
+    }
+
+    /// All recorded deposits and withdrawals for `client`, in no particular order.
+    pub fn find_transactions_for_client(
+        &self,
+        client: AccountID,
+    ) -> impl Iterator<Item = &Transaction> + '_ {
+        self.transactions
+            .values()
+            .filter(move |t| t.client() == client)
+    }
+
+    /// Ids of all recorded deposits and withdrawals for `client`, in the order they were recorded,
+    /// looked up without scanning every transaction. Returns `None` if
+    /// `BankConfig::enable_client_index` is not set, or the client has no recorded transactions.
+    pub fn client_transaction_ids(&self, client: AccountID) -> Option<&[TransactionID]> {
+        self.client_transactions.get(&client).map(Vec::as_slice)
+    }
+
+    /// Number of recorded deposits and withdrawals for `client`. Uses the client index if
+    /// `BankConfig::enable_client_index` is set, otherwise scans every transaction.
+    pub fn count_transactions_for_client(&self, client: AccountID) -> usize {
+        if self.config.enable_client_index {
+            return self.client_transaction_ids(client).map_or(0, <[_]>::len);
+        }
+        self.find_transactions_for_client(client).count()
+    }
+
+    /// Sum of all recorded deposits of `client`. Disputes do not affect the total.
+    pub fn deposit_total_for_client(&self, client: AccountID) -> Amount {
+        self.total_for_client(client, Kind::Deposit)
+    }
+
+    /// Sum of all recorded withdrawals of `client`.
+    pub fn withdrawal_total_for_client(&self, client: AccountID) -> Amount {
+        self.total_for_client(client, Kind::Withdrawal)
+    }
+
+    fn total_for_client(&self, client: AccountID, kind: Kind) -> Amount {
+        self.find_transactions_for_client(client)
+            .filter(|t| t.kind() == kind)
+            .map(Transaction::amount_or_zero)
+            .sum()
+    }
+
+    /// Ids of all recorded deposits and withdrawals for `client`, in no particular order.
+    pub fn transaction_ids_for_client(
+        &self,
+        client: AccountID,
+    ) -> impl Iterator<Item = TransactionID> + '_ {
+        self.find_transactions_for_client(client)
+            .map(Transaction::id)
+    }
+
+    /// Ids of all recorded transactions followed by the ids of all open disputes, in no particular
+    /// order. A disputed transaction is usually still recorded as well, so its id is yielded twice.
+    pub fn all_transaction_ids(&self) -> impl Iterator<Item = TransactionID> + '_ {
+        self.transactions
+            .keys()
+            .chain(self.disputes.keys())
+            .copied()
+    }
+
+    /// Sum of all fees paid across all accounts, see `Account::total_fees_paid`.
+    pub fn total_fees_collected(&self) -> Amount {
+        self.accounts
+            .values()
+            .map(Account::total_fees_paid)
+            .fold(Amount::new(), Amount::saturating_add)
+    }
+
+    /// Sum of total funds across all accounts.
+    pub fn total_assets(&self) -> Amount {
+        self.accounts
+            .values()
+            .map(Account::total)
+            .fold(Amount::new(), Amount::saturating_add)
+    }
+
+    /// Every recorded deposit and withdrawal whose amount is at least `min_amount`, e.g. for
+    /// regulatory large-transaction reporting.
+    pub fn compliance_report(&self, min_amount: Amount) -> ComplianceReport {
+        let mut transactions: Vec<_> = self
+            .transactions
+            .values()
+            .filter(|t| t.amount_or_zero() >= min_amount)
+            .map(|t| (t.id(), t.client(), t.amount_or_zero()))
+            .collect();
+        transactions.sort_unstable_by_key(|(id, _, _)| *id);
+
+        ComplianceReport {
+            threshold: min_amount,
+            transactions,
+        }
+    }
+
+    /// Ids of the recorded deposits and withdrawals matching `predicate`, sorted in ascending
+    /// order, e.g. for fraud analysis.
+    pub fn flagged_transactions<F>(&self, predicate: F) -> Vec<TransactionID>
+    where
+        F: Fn(&Transaction) -> bool,
+    {
+        let mut ids: Vec<TransactionID> = self
+            .transactions
+            .values()
+            .filter(|t| predicate(t))
+            .map(Transaction::id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The `n` accounts with the largest total balance, largest first. Ties are broken by the
+    /// smallest `AccountID`. Only `n` accounts are kept in a heap, rather than sorting all of them.
+    pub fn top_n_accounts_by_total(&self, n: usize) -> Vec<(AccountID, Amount)> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Min-heap of the best accounts seen so far, so the worst of them can be evicted.
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (id, account) in &self.accounts {
+            heap.push(Reverse((account.total(), Reverse(*id))));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((total, Reverse(id)))| (id, total))
+            .collect()
+    }
+
+    /// The `n` accounts with the smallest available balance, smallest first, e.g. to monitor for
+    /// overdrafts. Ties are broken by the smallest `AccountID`.
+    pub fn bottom_n_accounts_by_available(&self, n: usize) -> Vec<(AccountID, Amount)> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Max-heap of the lowest accounts seen so far, so the highest of them can be evicted.
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (id, account) in &self.accounts {
+            heap.push((account.available(), *id));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(available, id)| (id, available))
+            .collect()
+    }
+
+    /// Sum of held funds across all accounts.
+    pub fn total_held(&self) -> Amount {
+        self.accounts
+            .values()
+            .map(Account::held)
+            .fold(Amount::new(), Amount::saturating_add)
+    }
+
+    /// Summarize the accounts with ids in `from..=to`, e.g. for a shard that owns that range.
+    pub fn summarize_by_client_range(&self, from: AccountID, to: AccountID) -> RangeSummary {
+        let mut summary = RangeSummary::default();
+        for (_, account) in self
+            .accounts
+            .iter()
+            .filter(|(id, _)| (from..=to).contains(*id))
+        {
+            summary.account_count += 1;
+            summary.total_assets = summary.total_assets.saturating_add(account.total());
+            summary.total_held = summary.total_held.saturating_add(account.held());
+            if account.is_locked() {
+                summary.locked_count += 1;
+            }
+        }
+        summary
+    }
+
+    /// Sum of the amounts of all open disputes, i.e. the maximum the bank could lose if every open
+    /// dispute ended in a chargeback. Disputes are currently the only way funds become held, so
+    /// this matches `total_held`, but it is computed from the disputes themselves.
+    pub fn chargeback_exposure(&self) -> Amount {
+        // unwrap is safe because we only would have inserted into `self.disputes` if there was a
+        // valid amount.
+        self.disputes.values().map(|t| t.amount().unwrap()).sum()
+    }
+
+    /// The open dispute which has been open the longest, and how long it has been open. Ties are
+    /// broken by the smallest `TransactionID`.
+    pub fn oldest_dispute(&self) -> Option<(TransactionID, Duration)> {
+        self.dispute_opened_at
+            .iter()
+            .min_by_key(|(id, opened_at)| (**opened_at, **id))
+            .map(|(id, opened_at)| {
+                (
+                    *id,
+                    self.config
+                        .clock
+                        .now()
+                        .saturating_duration_since(*opened_at),
+                )
+            })
+    }
+
+    /// Whether `client` has any open disputes.
+    pub fn has_open_disputes_for_client(&self, client: AccountID) -> bool {
+        self.disputes.values().any(|t| t.client() == client)
+    }
+
+    /// How many open disputes `client` has.
+    pub fn count_open_disputes_for_client(&self, client: AccountID) -> usize {
+        self.disputes
+            .values()
+            .filter(|t| t.client() == client)
+            .count()
+    }
+
+    /// Balances, status, and activity of the account of `client`, e.g. for customer support.
+    /// Returns `None` if the client has no account.
+    pub fn account_summary(&self, client: AccountID) -> Option<AccountSummary> {
+        let account = self.accounts.get(&client)?;
+        Some(Self::summarize(
+            client,
+            account,
+            self.count_open_disputes_for_client(client),
+        ))
+    }
+
+    /// Same as `account_summary` for each of `clients`, omitting clients without an account. Open
+    /// disputes are counted in a single pass, rather than once per client.
+    pub fn bulk_account_summaries(
+        &self,
+        clients: &[AccountID],
+    ) -> HashMap<AccountID, AccountSummary> {
+        let open_disputes = self.count_disputes_per_account();
+        clients
+            .iter()
+            .filter_map(|client| {
+                let account = self.accounts.get(client)?;
+                let disputes = open_disputes.get(client).copied().unwrap_or(0);
+                Some((*client, Self::summarize(*client, account, disputes)))
+            })
+            .collect()
+    }
+
+    fn summarize(client: AccountID, account: &Account, open_disputes: usize) -> AccountSummary {
+        AccountSummary {
+            client,
+            available: account.available(),
+            held: account.held(),
+            total: account.total(),
+            is_locked: account.is_locked(),
+            is_frozen: account.is_frozen(),
+            open_disputes,
+            total_transactions: account.transaction_count(),
+            chargebacks: account.chargeback_history().len(),
+            fees_paid: account.total_fees_paid(),
+        }
+    }
+
+    /// Number of open disputes per client. Clients without any open disputes are not included.
+    pub fn count_disputes_per_account(&self) -> HashMap<AccountID, usize> {
+        let mut counts = HashMap::new();
+        for transaction in self.disputes.values() {
+            *counts.entry(transaction.client()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Accounts that require human review according to `BankConfig::review_thresholds`, along
+    /// with every reason they were flagged. Accounts without any flags are not yielded.
+    pub fn accounts_needing_review(
+        &self,
+    ) -> impl Iterator<Item = (AccountID, Vec<ReviewFlag>)> + '_ {
+        let thresholds = &self.config.review_thresholds;
+        let disputes = self.count_disputes_per_account();
+
+        self.accounts.iter().filter_map(move |(id, account)| {
+            let mut flags = Vec::new();
+
+            if account.is_locked() {
+                flags.push(ReviewFlag::Locked);
+            }
+
+            let open_disputes = disputes.get(id).copied().unwrap_or(0) as u32;
+            if open_disputes > thresholds.max_open_disputes {
+                flags.push(ReviewFlag::ExcessiveDisputes(open_disputes));
+            }
+
+            if account.available().is_negative() || account.total().is_negative() {
+                flags.push(ReviewFlag::NegativeBalance);
+            }
+
+            if account.held() > thresholds.max_held {
+                flags.push(ReviewFlag::LargeHeld(account.held()));
+            }
+
+            if flags.is_empty() {
+                None
+            } else {
+                Some((*id, flags))
+            }
+        })
+    }
+
+    /// Write gauges describing the bank in the Prometheus text exposition format.
+    #[cfg(feature = "prometheus")]
+    pub fn prometheus_metrics(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let locked = self.accounts.values().filter(|a| a.is_locked()).count();
+
+        let gauges: [(&str, &str, String); 5] = [
+            (
+                "banker_accounts_total",
+                "Number of accounts.",
+                self.accounts.len().to_string(),
+            ),
+            (
+                "banker_locked_accounts_total",
+                "Number of locked accounts.",
+                locked.to_string(),
+            ),
+            (
+                "banker_open_disputes_total",
+                "Number of open disputes.",
+                self.disputes.len().to_string(),
+            ),
+            (
+                "banker_total_assets",
+                "Sum of total funds across all accounts.",
+                self.total_assets().to_string(),
+            ),
+            (
+                "banker_total_held",
+                "Sum of held funds across all accounts.",
+                self.total_held().to_string(),
+            ),
+        ];
+
+        for (name, help, value) in gauges {
+            writeln!(writer, "# HELP {} {}", name, help)?;
+            writeln!(writer, "# TYPE {} gauge", name)?;
+            writeln!(writer, "{} {}", name, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compare this bank (the "before" state) against `other` (the "after" state).
+    pub fn diff(&self, other: &Bank) -> BankDiff {
+        let mut diff = BankDiff::default();
+
+        for (id, account) in &other.accounts {
+            match self.accounts.get(id) {
+                None => diff.new_accounts.push(*id),
+                Some(before) if before != account => {
+                    diff.modified_accounts
+                        .push((*id, before.clone(), account.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        diff.removed_accounts = self
+            .accounts
+            .keys()
+            .filter(|id| !other.accounts.contains_key(id))
+            .copied()
+            .collect();
+
+        diff.new_transactions = other
+            .transactions
+            .keys()
+            .filter(|id| !self.transactions.contains_key(id))
+            .copied()
+            .collect();
+
+        diff.resolved_disputes = self
+            .disputes
+            .keys()
+            .filter(|id| !other.disputes.contains_key(id))
+            .copied()
+            .collect();
+
+        diff.new_accounts.sort_unstable();
+        diff.removed_accounts.sort_unstable();
+        diff.modified_accounts
+            .sort_unstable_by_key(|(id, _, _)| *id);
+        diff.new_transactions.sort_unstable();
+        diff.resolved_disputes.sort_unstable();
+
+        diff
+    }
+
+    /// Create a new bank with `config` by re-applying every successful entry of an audit trail, in
+    /// order. Failed entries are skipped, so the result matches the bank the trail was captured
+    /// from as long as it was only modified through `process_transaction` and
+    /// `process_resolve_partial`, and `config` is the configuration of that bank, see `config`.
+    /// The configuration is not part of the trail, but decides whether entries apply, e.g. through
+    /// `BankConfig::allow_adjustments`, `max_transactions`, and `max_velocity`, so it has to be
+    /// passed in. Initial deposit bonuses are replayed from their own entries rather than granted
+    /// again.
+    pub fn replay_from_audit_trail(
+        entries: &[AuditEntry],
+        config: BankConfig,
+    ) -> Result<Bank, BankError> {
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: None,
+            ..config
+        });
+        for entry in entries.iter().filter(|e| e.outcome().is_ok()) {
+            let transaction = entry.transaction();
+            if entry.metadata().contains_key(GENERATED_ID_METADATA_KEY) {
+                bank.mark_generated_id(transaction.id());
+            }
+            match (transaction.kind(), transaction.amount()) {
+                // Resolves only carry an amount when recorded by `process_resolve_partial`.
+                (Kind::Resolve, Some(amount)) => {
+                    bank.process_resolve_partial(transaction.id(), amount)?
+                }
+                _ => {
+                    let origin = Origin::from_metadata(entry.metadata());
+                    bank.submit(transaction.clone(), origin, true, |e| e)?
+                }
+            }
+        }
+        Ok(bank)
+    }
+
+    /// Preview the effect of `transaction` on its client's account without modifying the bank. The
+    /// transaction is applied to a temporary copy of the bank. Returns an error if the client has
+    /// no account, even after the transaction.
+    pub fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, BankError> {
+        let mut bank = self.clone();
+        let would_succeed = bank.process_transaction(transaction.clone()).is_ok();
+
+        let account = bank
+            .accounts
+            .get(&transaction.client())
+            .ok_or_else(|| BankError::AccountNotFound(transaction.client()))?;
+        Ok(SimulationResult {
+            would_succeed,
+            new_available: account.available(),
+            new_held: account.held(),
+            new_total: account.total(),
+        })
+    }
+
+    /// Simulate `transaction` like `simulate_transaction`, additionally recording it in the audit
+    /// trail as a dry run if `BankConfig::record_audit_trail` is set. Nothing is applied.
+    pub fn process_transaction_dry_run(
+        &mut self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, BankError> {
+        let result = self.simulate_transaction(transaction);
+        if self.config.record_audit_trail {
+            let would_succeed = result.as_ref().is_ok_and(|r| r.would_succeed);
+            self.push_audit_entry(AuditEntry::dry_run(transaction.clone(), would_succeed));
+        }
+        result
+    }
+
+    /// The kinds of all transactions submitted through `process_transaction`, whether or not they
+    /// could be applied, or applied through `process_transaction_checked`. Empty unless
+    /// `BankConfig::track_kinds_seen` is set.
+    pub fn list_all_kinds_seen(&self) -> HashSet<Kind> {
+        self.kinds_seen.clone()
+    }
+
+    // Record `entry` in the audit trail, timestamped with the current time.
+    fn push_audit_entry(&mut self, entry: AuditEntry) {
+        let timestamp = self
+            .config
+            .clock
+            .now()
+            .saturating_duration_since(self.created_at);
+        self.audit_trail.push(entry.at(timestamp));
+    }
+
+    /// Write the audit trail as CSV with the columns
+    /// `timestamp,tx_id,client,kind,amount,outcome,metadata`, oldest entry first. The timestamp is
+    /// in milliseconds since the creation of the bank, the outcome is `ok` or the error, and the
+    /// metadata is a JSON object.
+    pub fn export_audit_trail_csv(&self, writer: impl Write) -> std::io::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.serialize((
+            "timestamp",
+            "tx_id",
+            "client",
+            "kind",
+            "amount",
+            "outcome",
+            "metadata",
+        ))?;
+        for entry in &self.audit_trail {
+            let transaction = entry.transaction();
+            let outcome = match entry.outcome() {
+                AuditOutcome::Ok => "ok".to_string(),
+                AuditOutcome::Err(err) => err.to_string(),
+                AuditOutcome::DryRun {
+                    would_succeed: true,
+                } => "dry run, would succeed".to_string(),
+                AuditOutcome::DryRun {
+                    would_succeed: false,
+                } => "dry run, would fail".to_string(),
+                AuditOutcome::ArchivedErr(message) => message.clone(),
+            };
+            // Sorted, so the output is stable.
+            let metadata: BTreeMap<_, _> = entry.metadata().iter().collect();
+            writer.serialize((
+                entry.timestamp().as_millis(),
+                transaction.id(),
+                transaction.client(),
+                transaction.kind(),
+                transaction.amount(),
+                outcome,
+                serde_json::to_string(&metadata)?,
+            ))?;
+        }
+        writer.flush()
+    }
+
+    /// Read back an audit trail written by `export_audit_trail_csv`, e.g. for analysis or to pass
+    /// to `replay_from_audit_trail`. Errors are only archived as their message, so failed entries
+    /// have an `AuditOutcome::ArchivedErr` outcome.
+    pub fn import_audit_trail_csv(reader: impl Read) -> Result<Vec<AuditEntry>, ImportError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+
+        let mut entries = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| ImportError::MalformedRow {
+                line: e.position().map_or(0, |p| p.line()),
+            })?;
+            let line = record.position().map_or(0, |p| p.line());
+            let malformed = ImportError::MalformedRow { line };
+            let invalid = ImportError::InvalidAmount { line };
+
+            let (timestamp, tx_id, client, kind, amount, outcome, metadata) =
+                match record.iter().collect::<Vec<_>>()[..] {
+                    [timestamp, tx_id, client, kind, amount, outcome, metadata] => {
+                        (timestamp, tx_id, client, kind, amount, outcome, metadata)
+                    }
+                    _ => return Err(malformed),
+                };
+            let timestamp: u64 = timestamp.parse().map_err(|_| malformed.clone())?;
+            let tx_id: TransactionID = tx_id.parse().map_err(|_| malformed.clone())?;
+            let client: AccountID = client.parse().map_err(|_| malformed.clone())?;
+            let kind = Kind::deserialize(kind.into_deserializer())
+                .map_err(|_: serde::de::value::Error| malformed.clone())?;
+            let metadata: HashMap<String, String> =
+                serde_json::from_str(metadata).map_err(|_| malformed.clone())?;
+            let amount: Option<Amount> = match amount {
+                "" => None,
+                amount => Some(amount.parse().map_err(|_| invalid)?),
+            };
+            let outcome = match outcome {
+                "" => return Err(malformed),
+                "ok" => AuditOutcome::Ok,
+                "dry run, would succeed" => AuditOutcome::DryRun {
+                    would_succeed: true,
+                },
+                "dry run, would fail" => AuditOutcome::DryRun {
+                    would_succeed: false,
+                },
+                message => AuditOutcome::ArchivedErr(message.to_string()),
+            };
+
+            let transaction = Transaction::new(kind, client, tx_id, amount);
+            entries.push(
+                AuditEntry::with_outcome(transaction, outcome)
+                    .with_metadata(metadata)
+                    .at(Duration::from_millis(timestamp)),
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Every transaction processed so far and its outcome, oldest first. Empty unless
+    /// `BankConfig::record_audit_trail` is set.
+    pub fn audit_trail(&self) -> &[AuditEntry] {
+        &self.audit_trail
+    }
+
+    /// The audit trail entries recorded from position `checkpoint` onward, for consuming the trail
+    /// incrementally. Callers should keep `checkpoint` plus the length of the returned slice as
+    /// their next checkpoint. Empty if `checkpoint` is past the end of the trail.
+    pub fn events_since(&self, checkpoint: usize) -> &[AuditEntry] {
+        self.audit_trail.get(checkpoint..).unwrap_or_default()
+    }
+
+    /// The current length of the audit trail, to be saved as a cursor for `Bank::events_since`.
+    pub fn checkpoint_audit_trail(&self) -> usize {
+        self.audit_trail.len()
+    }
+
+    /// Process every transaction in order, collecting the ids and errors of the ones which could
+    /// not be applied.
+    pub fn process_all(
+        &mut self,
+        transactions: impl IntoIterator<Item = Transaction>,
+    ) -> ProcessResult {
+        let mut result = ProcessResult::default();
+        for transaction in transactions {
+            let tx_id = transaction.id();
+            match self.process_transaction(transaction) {
+                Ok(()) => result.succeeded += 1,
+                Err(e) => result.failed.push((tx_id, e)),
+            }
+        }
+        result
+    }
+
+    /// Same as `process_all`, for transactions which may have failed to parse, e.g. the rows of a
+    /// CSV reader. Items which are errors are counted and skipped.
+    pub fn process_from_iterator<E>(
+        &mut self,
+        transactions: impl IntoIterator<Item = Result<Transaction, E>>,
+    ) -> ProcessResult {
+        let mut parse_errors = 0;
+        let mut result = self.process_all(transactions.into_iter().filter_map(|t| {
+            if t.is_err() {
+                parse_errors += 1;
+            }
+            t.ok()
+        }));
+        result.parse_errors = parse_errors;
+        result
+    }
+
+    /// Process every transaction of a CSV file with the columns `type,client,tx,amount`, in order.
+    /// Whitespace around values is ignored, and the amount may be left out for kinds that do not
+    /// have one. Stops at the first row which can not be parsed.
+    pub fn import_transactions_csv(&mut self, reader: impl Read) -> csv::Result<ProcessResult> {
+        self.import_csv_as(reader, None)
+    }
+
+    /// Same as `import_transactions_csv`, for a reversals file in the same format. Every
+    /// transaction is processed as a `Kind::AdminReversal` of the transaction it references,
+    /// whatever its `type` column says.
+    pub fn process_reversals_from_csv(&mut self, reader: impl Read) -> csv::Result<ProcessResult> {
+        self.import_csv_as(reader, Some(Kind::AdminReversal))
+    }
+
+    // Process every transaction in the CSV, overriding its kind with `kind` if given.
+    fn import_csv_as(
+        &mut self,
+        reader: impl Read,
+        kind: Option<Kind>,
+    ) -> csv::Result<ProcessResult> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut result = ProcessResult::default();
+        for transaction in reader.deserialize() {
+            let mut transaction: Transaction = transaction?;
+            if let Some(kind) = kind {
+                transaction.set_kind(kind);
+            }
+            let tx_id = transaction.id();
+            match self.process_transaction(transaction) {
+                Ok(()) => result.succeeded += 1,
+                Err(e) => result.failed.push((tx_id, e)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Same as `import_transactions_csv`, reading from stdin. Blocks until stdin is closed.
+    pub fn process_csv_from_stdin(&mut self) -> Result<ProcessResult, Box<dyn Error>> {
+        let stdin = std::io::stdin();
+        Ok(self.import_transactions_csv(BufReader::new(stdin.lock()))?)
+    }
+
+    /// Check the invariants which should hold between accounts and disputes after any sequence of
+    /// operations.
+    pub fn validate_consistency(&self) -> Result<(), ConsistencyError> {
+        let mut disputed: HashMap<AccountID, Option<Amount>> = HashMap::new();
+        for (id, transaction) in &self.disputes {
+            if !self.transactions.contains_key(id) {
+                return Err(ConsistencyError::DisputeWithoutTransaction(*id));
+            }
+            // unwrap is safe because we only would have inserted into `self.disputes` if there was
+            // a valid amount.
+            let total = disputed
+                .entry(transaction.client())
+                .or_insert(Some(Amount::new()));
+            *total = total.and_then(|t| t.checked_add(transaction.amount().unwrap()));
+        }
+
+        for (id, account) in &self.accounts {
+            if account.held().is_negative() {
+                return Err(ConsistencyError::NegativeHeld(*id));
+            }
+            if account.available() < -account.withdrawal_limit().unwrap_or_default() {
+                return Err(ConsistencyError::BelowLimit(*id));
+            }
+            let held = disputed.get(id).copied().unwrap_or(Some(Amount::new()));
+            if held != Some(account.held()) {
+                return Err(ConsistencyError::HeldMismatch(*id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the invariants of a single recorded or disputed transaction: a dispute must reference
+    /// a recorded transaction, the amount must be positive, and the client must have an account.
+    /// Cheaper than `validate_consistency` when only one transaction is of interest.
+    pub fn verify_transaction_integrity(&self, tx_id: TransactionID) -> Result<(), IntegrityError> {
+        let transaction = match (
+            self.transactions.get(&tx_id),
+            self.disputes.contains_key(&tx_id),
+        ) {
+            (Some(transaction), _) => transaction,
+            (None, true) => return Err(IntegrityError::DisputeWithoutTransaction(tx_id)),
+            (None, false) => return Err(IntegrityError::TransactionNotFound(tx_id)),
+        };
+        if transaction.amount().is_none_or(|a| a <= Amount::new()) {
+            return Err(IntegrityError::InvalidAmount(tx_id));
+        }
+        if !self.accounts.contains_key(&transaction.client()) {
+            return Err(IntegrityError::AccountNotFound(transaction.client()));
+        }
+        Ok(())
+    }
+
+    /// Ids of all accounts without an account limit whose available funds are negative, sorted.
+    /// Only accounts with a limit may be overdrawn, so this is always empty for a correctly
+    /// functioning bank.
+    pub fn enforce_non_negative_invariant(&self) -> Vec<AccountID> {
+        let mut violations: Vec<_> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                account.withdrawal_limit().is_none() && account.available().is_negative()
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        violations.sort_unstable();
+        violations
+    }
+
+    /// Check that the funds across all accounts match the net funds moved into the bank by
+    /// deposits, withdrawals, reversals, and chargebacks. Balances of accounts seeded through
+    /// `with_accounts` were never moved into the bank, so this only holds for banks built from
+    /// transactions.
+    pub fn reconcile(&self) -> Result<(), ConsistencyError> {
+        let actual = self.accounts.values().fold(Amount::new(), |sum, account| {
+            sum.wrapping_add(account.total())
+        });
+
+        if actual != self.net_flow {
+            return Err(ConsistencyError::LedgerMismatch {
+                expected: self.net_flow,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        self.process_transaction_with_metadata(transaction, HashMap::new())
+    }
+
+    /// Same as `process_transaction`, but `metadata` (e.g. a webhook delivery id or the operator)
+    /// is stored with the audit trail entry, if `BankConfig::record_audit_trail` is set.
+    pub fn process_transaction_with_metadata(
+        &mut self,
+        transaction: Transaction,
+        metadata: HashMap<String, String>,
+    ) -> Result<(), BankError> {
+        self.submit(transaction, Origin::Client, true, |entry| {
+            entry.with_metadata(metadata)
+        })
+    }
+
+    // Apply `transaction` along with the bookkeeping shared by the entry points: the kind and
+    // error-rate statistics, the audit trail entry, passed through `annotate` first, and the
+    // initial deposit bonus of new accounts. Entries of bank initiated transactions are tagged
+    // with `ORIGIN_METADATA_KEY`, and those with generated ids with `GENERATED_ID_METADATA_KEY`,
+    // so `replay_from_audit_trail` can tell them apart. Unless
+    // `record_failure` is set, a transaction which fails leaves no trace at all.
+    fn submit(
+        &mut self,
+        transaction: Transaction,
+        origin: Origin,
+        record_failure: bool,
+        annotate: impl FnOnce(AuditEntry) -> AuditEntry,
+    ) -> Result<(), BankError> {
+        let kind = transaction.kind();
+        let client = transaction.client();
+        let generated_id = self.generated_ids.contains(&transaction.id());
+        let new_account = kind == Kind::Deposit && !self.accounts.contains_key(&client);
+        let audited = self.config.record_audit_trail.then(|| transaction.clone());
+        let result = self.apply_transaction(transaction, origin);
+        if result.is_err() && !record_failure {
+            return result;
+        }
+
+        if self.config.track_kinds_seen {
+            self.kinds_seen.insert(kind);
+        }
+        self.record_outcome(result.is_err());
+
+        if let Some(transaction) = audited {
+            let mut entry = annotate(AuditEntry::new(transaction, result.clone()));
+            if let Some(value) = origin.metadata_value() {
+                entry = entry.with_metadata_entry(ORIGIN_METADATA_KEY, value);
+            }
+            if generated_id {
+                entry = entry.with_metadata_entry(GENERATED_ID_METADATA_KEY, "true");
+            }
+            self.push_audit_entry(entry);
+        }
+        if new_account && result.is_ok() {
+            self.apply_initial_deposit_bonus(client);
+        }
+        result
+    }
+
+    /// Same as `process_transaction`, but the transaction is checked before it is applied: it must
+    /// pass `Transaction::validate`, and deposits and withdrawals may not reuse a recorded id or
+    /// target a locked account. Every other rule is checked by the operation itself before it
+    /// changes anything. If an error is returned the bank is left exactly as it was, so unlike
+    /// `process_transaction` failures are not added to the audit trail or the error-rate and kind
+    /// statistics.
+    pub fn process_transaction_checked(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<(), BankError> {
+        transaction.validate()?;
+        if matches!(transaction.kind(), Kind::Deposit | Kind::Withdrawal) {
+            if self.transactions.contains_key(&transaction.id()) {
+                return Err(BankError::DuplicateTransaction(transaction.id()));
+            }
+            if self
+                .accounts
+                .get(&transaction.client())
+                .is_some_and(Account::is_locked)
+            {
+                return Err(AccountError::AccountLocked.into());
+            }
+        }
+
+        self.submit(transaction, Origin::Client, false, |entry| entry)
+    }
+
+    /// Same as `process_transaction`, but the transaction is only applied once per `idem_key`, an
+    /// external key chosen by the caller such as a request UUID. Keys of transactions which fail
+    /// are not remembered, so they can be retried. Only the most recently used
+    /// `BankConfig::max_idempotency_cache` keys are remembered.
+    pub fn process_transaction_with_idempotency(
+        &mut self,
+        transaction: Transaction,
+        idem_key: &str,
+    ) -> Result<IdempotencyOutcome, BankError> {
+        if self.idempotency_keys.keys.get(idem_key).is_some() {
+            return Ok(IdempotencyOutcome::AlreadySeen);
+        }
+
+        self.process_transaction(transaction)?;
+        self.idempotency_keys.keys.put(idem_key.to_string(), ());
+        Ok(IdempotencyOutcome::Applied)
+    }
+
+    /// Forget every idempotency key, so that any key is treated as new.
+    pub fn flush_idempotency_cache(&mut self) {
+        self.idempotency_keys.keys.clear();
+    }
+
+    fn apply_transaction(
+        &mut self,
+        transaction: Transaction,
+        origin: Origin,
+    ) -> Result<(), BankError> {
+        let amount = transaction.amount_or_zero();
+        if origin == Origin::Client {
+            self.check_not_frozen(transaction.client())?;
+            self.check_velocity(transaction.client())?;
+        }
+
+        let client = transaction.client();
+        let tx_id = transaction.id();
+        let kind = transaction.kind();
+        // Disputes, resolves, and chargebacks are notified with the amount currently disputed.
+        let event_amount = match kind {
+            Kind::Dispute => self
+                .transactions
+                .get(&tx_id)
+                .map(Transaction::amount_or_zero),
+            Kind::Resolve | Kind::Chargeback => {
+                self.disputes.get(&tx_id).map(Transaction::amount_or_zero)
+            }
+            _ => Some(amount),
+        }
+        .unwrap_or_default();
+        let entry = StatementEntry {
+            tx_id: transaction.id(),
+            kind: transaction.kind(),
+            amount: transaction.amount(),
+            balance_after: Amount::new(),
+            memo: transaction.memo().map(str::to_string),
+        };
+
+        match transaction.kind() {
+            Kind::Deposit => self.process_deposit(transaction),
+            Kind::Withdrawal => self.process_withdrawl(transaction),
+            Kind::Dispute => self.process_dispute(transaction),
+            Kind::Resolve => self.process_resolve(transaction),
+            Kind::Chargeback => self.process_chargeback(transaction),
+            Kind::AdminReversal => self.process_admin_reversal(transaction),
+            Kind::Adjustment => self.process_adjustment(transaction),
+        }?;
+
+        self.record_statement(client, entry);
+        if origin == Origin::Client {
+            if let Some(account) = self.accounts.get_mut(&client) {
+                account.record_applied_transaction();
+            }
+            self.record_recent(client, amount);
+        }
+        if origin == Origin::Fee {
+            if let Some(account) = self.accounts.get_mut(&client) {
+                account.record_fee(amount);
+            }
+        }
+        self.handlers.notify(|handler| match kind {
+            Kind::Deposit => handler.on_deposit(client, tx_id, event_amount),
+            Kind::Withdrawal => handler.on_withdrawal(client, tx_id, event_amount),
+            Kind::Dispute => handler.on_dispute(client, tx_id, event_amount),
+            Kind::Resolve => handler.on_resolve(client, tx_id, event_amount),
+            Kind::Chargeback => handler.on_chargeback(client, tx_id, event_amount),
+            Kind::AdminReversal | Kind::Adjustment => {}
+        });
+        Ok(())
+    }
+
+    // Add `entry` to the statement of `client`, with the balance after it, if
+    // `BankConfig::record_statements` is set.
+    fn record_statement(&mut self, client: AccountID, mut entry: StatementEntry) {
+        if !self.config.record_statements {
+            return;
+        }
+        if let Some(account) = self.accounts.get_mut(&client) {
+            entry.balance_after = account.total();
+            self.statements.entry(client).or_default().push(entry);
+        }
+    }
+
+    // Credit `BankConfig::initial_deposit_bonus` to a new account after its first deposit. The
+    // first deposit was already applied, so a bonus which can not be credited, e.g. because the
+    // bank is full, is skipped rather than failing the deposit. The bonus is recorded in the audit
+    // trail, but does not count towards the client's velocity or transaction count.
+    fn apply_initial_deposit_bonus(&mut self, client: AccountID) {
+        if let Some(bonus) = self.config.initial_deposit_bonus {
+            let tx_id = self.generate_transaction_id();
+            let transaction = Transaction::new(Kind::Deposit, client, tx_id, Some(bonus))
+                .with_memo("initial deposit bonus");
+            let _ = self.submit(transaction, Origin::Bank, true, |entry| entry);
+        }
+    }
+
+    /// Register a handler to be notified of every operation this bank successfully applies from
+    /// now on, returning an id which can be passed to `unsubscribe`. Handlers are not carried over
+    /// when the bank is cloned.
+    pub fn subscribe(&mut self, handler: Box<dyn TransactionEventHandler + Send>) -> usize {
+        self.handlers.push(handler)
+    }
+
+    /// Remove a handler registered with `subscribe`, returning whether it was found.
+    pub fn unsubscribe(&mut self, handler_id: usize) -> bool {
+        self.handlers.remove(handler_id)
+    }
+
+    // Frozen accounts reject every operation. Accounts which do not exist are left to the
+    // operation to handle.
+    fn check_not_frozen(&self, client: AccountID) -> Result<(), BankError> {
+        match self.accounts.get(&client) {
+            Some(account) if account.is_frozen() => Err(AccountError::AccountFrozen.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_outcome(&mut self, failed: bool) {
+        if self.config.recent_transactions == 0 {
+            return;
+        }
+        if self.errors_in_window.len() == self.config.recent_transactions {
+            self.errors_in_window.pop_front();
+        }
+        self.errors_in_window.push_back(failed);
+    }
+
+    /// The fraction of the last `window` transactions submitted through `process_transaction`
+    /// which failed, or zero if none were submitted. Transactions applied through
+    /// `process_transaction_checked` count as submitted, but its failures do not. Only the last
+    /// `BankConfig::recent_transactions` are remembered, so larger windows are truncated.
+    pub fn transaction_error_rate(&self, window: usize) -> f64 {
+        let outcomes = self.errors_in_window.iter().rev().take(window);
+        let (total, failed) = outcomes.fold((0, 0), |(total, failed), failed_here| {
+            (total + 1, failed + u32::from(*failed_here))
+        });
+        if total == 0 {
+            return 0.0;
+        }
+        f64::from(failed) / f64::from(total)
+    }
+
+    /// Forget the outcomes behind `transaction_error_rate`, e.g. after a known bad period.
+    pub fn reset_error_rate_counters(&mut self) {
+        self.errors_in_window.clear();
+    }
+
+    fn check_velocity(&self, client: AccountID) -> Result<(), BankError> {
+        match self.config.max_velocity {
+            Some((max, window)) if self.transaction_velocity(client, window) >= max => {
+                Err(BankError::VelocityLimitExceeded(client))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record_recent(&mut self, client: AccountID, amount: Amount) {
+        if self.config.recent_transactions == 0 {
+            return;
+        }
+        if self.recent.len() == self.config.recent_transactions {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((client, amount));
+    }
+
+    /// Total amount of the last `window` successfully applied transactions. Only the last
+    /// `BankConfig::recent_transactions` are remembered, so larger windows are truncated.
+    pub fn transaction_volume(&self, window: usize) -> Amount {
+        self.recent
+            .iter()
+            .rev()
+            .take(window)
+            .map(|(_, amount)| *amount)
+            .sum()
+    }
+
+    /// How many of the last `window` successfully applied transactions, across all clients,
+    /// belong to `client`. Like `transaction_volume`, larger windows than
+    /// `BankConfig::recent_transactions` are truncated.
+    pub fn transaction_velocity(&self, client: AccountID, window: usize) -> u32 {
+        self.recent
+            .iter()
+            .rev()
+            .take(window)
+            .filter(|(id, _)| *id == client)
+            .count() as u32
+    }
+
+    /// Convenience for `process_transaction` with a `Kind::Deposit` transaction.
+    pub fn process_deposit_raw(
+        &mut self,
+        client: AccountID,
+        tx_id: TransactionID,
+        amount: Amount,
+    ) -> Result<(), BankError> {
+        self.process_transaction(Transaction::new(Kind::Deposit, client, tx_id, Some(amount)))
+    }
+
+    /// Convenience for `process_transaction` with a `Kind::Withdrawal` transaction.
+    pub fn process_withdrawal_raw(
+        &mut self,
+        client: AccountID,
+        tx_id: TransactionID,
+        amount: Amount,
+    ) -> Result<(), BankError> {
+        self.process_transaction(Transaction::new(
+            Kind::Withdrawal,
+            client,
+            tx_id,
+            Some(amount),
+        ))
+    }
+
+    /// Convenience for `process_transaction` with a `Kind::Dispute` transaction.
+    pub fn process_dispute_raw(
+        &mut self,
+        client: AccountID,
+        tx_id: TransactionID,
+    ) -> Result<(), BankError> {
+        self.process_transaction(Transaction::new(Kind::Dispute, client, tx_id, None))
+    }
+
+    /// Convenience for `process_transaction` with a `Kind::Resolve` transaction.
+    pub fn process_resolve_raw(
+        &mut self,
+        client: AccountID,
+        tx_id: TransactionID,
+    ) -> Result<(), BankError> {
+        self.process_transaction(Transaction::new(Kind::Resolve, client, tx_id, None))
+    }
+
+    /// Convenience for `process_transaction` with a `Kind::Chargeback` transaction.
+    pub fn process_chargeback_raw(
+        &mut self,
+        client: AccountID,
+        tx_id: TransactionID,
+    ) -> Result<(), BankError> {
+        self.process_transaction(Transaction::new(Kind::Chargeback, client, tx_id, None))
+    }
+
+    /// Admin operation to partially settle an open dispute, releasing `partial_amount` from held
+    /// back to available. The dispute stays open for the remaining amount, unless the full amount
+    /// was released in which case it is closed. The amount must be positive. Recorded in the audit
+    /// trail as a resolve carrying the released amount.
+    pub fn process_resolve_partial(
+        &mut self,
+        tx_id: TransactionID,
+        partial_amount: Amount,
+    ) -> Result<(), BankError> {
+        let client = self
+            .disputes
+            .get(&tx_id)
+            .ok_or(BankError::DisputeNotFound(tx_id))?
+            .client();
+        let result = self.apply_resolve_partial(tx_id, partial_amount);
+
+        if self.config.record_audit_trail {
+            let transaction = Transaction::new(Kind::Resolve, client, tx_id, Some(partial_amount));
+            self.push_audit_entry(AuditEntry::new(transaction, result.clone()));
+        }
+        result
+    }
+
+    fn apply_resolve_partial(
+        &mut self,
+        tx_id: TransactionID,
+        partial_amount: Amount,
+    ) -> Result<(), BankError> {
+        if partial_amount.is_negative() {
+            return Err(BankError::NegativeAmount);
+        }
+        if partial_amount == Amount::new() {
+            return Err(AccountError::NonPositiveAmount.into());
+        }
+        let dispute = self
+            .disputes
+            .get_mut(&tx_id)
+            .ok_or(BankError::DisputeNotFound(tx_id))?;
+
+        // unwrap is safe because we only would have inserted into `self.disputes` if there was a
+        // valid amount.
+        let disputed = dispute.amount().unwrap();
+        if partial_amount > disputed {
+            return Err(BankError::ExceedsDisputedAmount);
+        }
+
+        let client = dispute.client();
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .ok_or(BankError::AccountNotFound(client))?;
+        if account.is_frozen() {
+            return Err(AccountError::AccountFrozen.into());
+        }
+        account.try_partial_resolve(partial_amount)?;
+
+        if partial_amount == disputed {
+            self.remove_dispute(tx_id);
+            self.resolved.insert(tx_id);
+        } else {
+            dispute.set_amount(Some(disputed - partial_amount));
+        }
+        self.record_statement(
+            client,
+            StatementEntry {
+                tx_id,
+                kind: Kind::Resolve,
+                amount: Some(partial_amount),
+                balance_after: Amount::new(),
+                memo: None,
+            },
+        );
+        self.handlers
+            .notify(|handler| handler.on_resolve(client, tx_id, partial_amount));
+
+        Ok(())
+    }
+
+    /// Credit many accounts at once, e.g. for payroll. Each credit is processed as a deposit with a
+    /// generated transaction id, and a failed credit does not stop the remaining ones.
+    pub fn apply_bulk_credit(
+        &mut self,
+        credits: impl Iterator<Item = (AccountID, Amount)>,
+    ) -> BulkResult {
+        let mut result = BulkResult::default();
+        for (client, amount) in credits {
+            let tx_id = self.generate_transaction_id();
+            match self.process_deposit_raw(client, tx_id, amount) {
+                Ok(()) => {
+                    result.succeeded += 1;
+                    result.total_credited = result.total_credited.saturating_add(amount);
+                }
+                Err(e) => result.failed.push((client, e)),
+            }
+        }
+        result
+    }
+
+    /// Dispute many transactions of `client` at once, e.g. in a fraud case. Each dispute is
+    /// processed independently, and a failed dispute does not stop the remaining ones.
+    pub fn batch_dispute(
+        &mut self,
+        tx_ids: &[TransactionID],
+        client: AccountID,
+    ) -> BatchDisputeResult {
+        let mut result = BatchDisputeResult::default();
+        for &tx_id in tx_ids {
+            match self.process_dispute_raw(client, tx_id) {
+                Ok(()) => result.succeeded.push(tx_id),
+                Err(e) => result.failed.push((tx_id, e)),
+            }
+        }
+        result
+    }
+
+    /// Resolve many open disputes at once, releasing their held funds. Each resolve is processed
+    /// independently, and a failed resolve does not stop the remaining ones.
+    pub fn batch_resolve(&mut self, tx_ids: &[TransactionID]) -> BatchResolveResult {
+        self.batch_close_disputes(tx_ids, Kind::Resolve)
+    }
+
+    /// Charge back many open disputes at once, locking their accounts. Each chargeback is
+    /// processed independently, and a failed chargeback does not stop the remaining ones.
+    pub fn batch_chargeback(&mut self, tx_ids: &[TransactionID]) -> BatchChargebackResult {
+        self.batch_close_disputes(tx_ids, Kind::Chargeback)
+    }
+
+    fn batch_close_disputes(&mut self, tx_ids: &[TransactionID], kind: Kind) -> BatchDisputeResult {
+        let mut result = BatchDisputeResult::default();
+        for &tx_id in tx_ids {
+            let outcome = match self.disputes.get(&tx_id) {
+                Some(dispute) => {
+                    let client = dispute.client();
+                    self.process_transaction(Transaction::new(kind, client, tx_id, None))
+                }
+                None => Err(BankError::DisputeNotFound(tx_id)),
+            };
+            match outcome {
+                Ok(()) => result.succeeded.push(tx_id),
+                Err(e) => result.failed.push((tx_id, e)),
+            }
+        }
+        result
+    }
+
+    /// Debit many accounts at once, e.g. for fee collection. Each debit is processed as a
+    /// withdrawal with a generated transaction id, and a failed debit does not stop the remaining
+    /// ones.
+    pub fn apply_bulk_debit(
+        &mut self,
+        debits: impl Iterator<Item = (AccountID, Amount)>,
+    ) -> BulkResult {
+        let mut result = BulkResult::default();
+        for (client, amount) in debits {
+            let tx_id = self.generate_transaction_id();
+            match self.process_withdrawal_raw(client, tx_id, amount) {
+                Ok(()) => {
+                    result.succeeded += 1;
+                    result.total_debited = result.total_debited.saturating_add(amount);
+                }
+                Err(e) => result.failed.push((client, e)),
+            }
+        }
+        result
+    }
+
+    /// Charge `client` a fee of `pct` percent of their available funds, see `Amount::percentage`.
+    /// The fee is processed as a withdrawal with a generated transaction id and a "fee" memo, and
+    /// the debited amount is returned. Accounts without positive available funds are charged
+    /// nothing.
+    pub fn apply_percentage_fee(
+        &mut self,
+        client: AccountID,
+        pct: f64,
+    ) -> Result<Amount, BankError> {
+        let available = self
+            .accounts
+            .get(&client)
+            .ok_or(BankError::AccountNotFound(client))?
+            .available();
+        let fee = available.max(Amount::new()).percentage(pct);
+        self.charge_fee(client, fee, "fee")?;
+        Ok(fee)
+    }
+
+    // Debit `fee` from `client` as a withdrawal with a generated transaction id, and record it in
+    // the account's fee total. Zero fees are not charged at all.
+    fn charge_fee(&mut self, client: AccountID, fee: Amount, memo: &str) -> Result<(), BankError> {
+        if fee == Amount::new() {
+            return Ok(());
+        }
+        let tx_id = self.generate_transaction_id();
+        let transaction =
+            Transaction::new(Kind::Withdrawal, client, tx_id, Some(fee)).with_memo(memo);
+        self.submit(transaction, Origin::Fee, true, |entry| entry)
+    }
+
+    /// Apply the periodic batch operations configured in `config`, in the order they are listed
+    /// there: maintenance fees, interest, dispute expiry, and review flags. An operation which
+    /// fails for one account does not stop the remaining ones.
+    pub fn process_end_of_period(&mut self, config: PeriodConfig) -> PeriodReport {
+        let mut report = PeriodReport::default();
+        let mut clients: Vec<AccountID> = self.accounts.keys().copied().collect();
+        clients.sort_unstable();
+
+        if let Some(fee) = config.maintenance_fee {
+            for &client in &clients {
+                match self.charge_fee(client, fee, "maintenance fee") {
+                    Ok(()) => report.fees_collected = report.fees_collected.saturating_add(fee),
+                    Err(e) => report.failed.push((client, e)),
+                }
+            }
+        }
+
+        if let Some(rate) = config.interest_rate {
+            for &client in &clients {
+                let interest = self.accounts[&client]
+                    .available()
+                    .max(Amount::new())
+                    .percentage(rate);
+                if interest == Amount::new() {
+                    continue;
+                }
+                let tx_id = self.generate_transaction_id();
+                let transaction = Transaction::new(Kind::Deposit, client, tx_id, Some(interest))
+                    .with_memo("interest");
+                match self.submit(transaction, Origin::Bank, true, |entry| entry) {
+                    Ok(()) => report.interest_paid = report.interest_paid.saturating_add(interest),
+                    Err(e) => report.failed.push((client, e)),
+                }
+            }
+        }
+
+        if let Some(max_age) = config.expire_old_disputes {
+            let now = self.config.clock.now();
+            let mut expired: Vec<(TransactionID, AccountID)> = self
+                .disputes
+                .values()
+                .filter(|t| {
+                    self.dispute_opened_at
+                        .get(&t.id())
+                        .is_some_and(|opened_at| {
+                            now.saturating_duration_since(*opened_at) >= max_age
+                        })
+                })
+                .map(|t| (t.id(), t.client()))
+                .collect();
+            expired.sort_unstable();
+
+            for (id, client) in expired {
+                match self.close_dispute_with_reason(id, DisputeResolution::AutoExpired, false) {
+                    Ok(()) => report.expired_disputes.push(id),
+                    Err(e) => report.failed.push((client, e)),
+                }
+            }
+        }
+
+        if config.flag_suspicious_accounts {
+            let mut flagged: Vec<AccountID> =
+                self.accounts_needing_review().map(|(id, _)| id).collect();
+            flagged.sort_unstable();
+            for id in &flagged {
+                // unwrap is safe because the ids were just taken from `self.accounts`.
+                self.accounts.get_mut(id).unwrap().flag_for_review();
+            }
+            report.flagged_accounts = flagged;
+        }
+
+        report
+    }
+
+    // Find an id which is not used by any recorded transaction.
+    fn generate_transaction_id(&mut self) -> TransactionID {
+        while self.transactions.contains_key(&self.next_generated_id) {
+            self.next_generated_id = self.next_generated_id.wrapping_sub(1);
+        }
+        let id = self.next_generated_id;
+        self.mark_generated_id(id);
+        id
+    }
+
+    // Record `id` as generated, and continue generating ids below it.
+    fn mark_generated_id(&mut self, id: TransactionID) {
+        self.generated_ids.insert(id);
+        self.next_generated_id = self.next_generated_id.min(id.wrapping_sub(1));
+    }
+
+    fn is_generated_id(&self, id: TransactionID) -> bool {
+        self.generated_ids.contains(&id)
+    }
+
+    /// Admin operation to require withdrawals to leave `client` with at least `minimum` available.
+    /// A zero minimum removes the requirement. Funds already below the minimum are left as they
+    /// are, but can not be withdrawn from further.
+    pub fn set_minimum_balance(
+        &mut self,
+        client: AccountID,
+        minimum: Amount,
+    ) -> Result<(), BankError> {
+        if minimum.is_negative() {
+            return Err(BankError::NegativeAmount);
+        }
+        self.accounts
+            .get_mut(&client)
+            .ok_or(BankError::AccountNotFound(client))?
+            .set_minimum_balance((minimum != Amount::new()).then_some(minimum));
+        Ok(())
+    }
+
+    /// Admin operation to let withdrawals take `client` up to `limit` below zero.
+    pub fn set_account_limit(&mut self, client: AccountID, limit: Amount) -> Result<(), BankError> {
+        if limit.is_negative() {
+            return Err(BankError::NegativeAmount);
+        }
+        self.accounts
+            .get_mut(&client)
+            .ok_or(BankError::AccountNotFound(client))?
+            .set_withdrawal_limit(Some(limit));
+        Ok(())
+    }
+
+    /// Admin operation to freeze `client`, rejecting all of its transactions until it is unfrozen.
+    pub fn freeze_account(&mut self, client: AccountID) -> Result<(), BankError> {
+        self.accounts
+            .get_mut(&client)
+            .ok_or(BankError::AccountNotFound(client))?
+            .freeze();
+        Ok(())
+    }
+
+    pub fn unfreeze_account(&mut self, client: AccountID) -> Result<(), BankError> {
+        self.accounts
+            .get_mut(&client)
+            .ok_or(BankError::AccountNotFound(client))?
+            .unfreeze();
+        Ok(())
+    }
+
+    /// Close an open dispute, recording why it was closed in the audit trail and in the account's
+    /// dispute history. A dispute closed with `reverse_funds` is charged back, otherwise it is
+    /// resolved. Closing is an admin operation, so it applies even if the account is frozen or
+    /// over its velocity limit.
+    pub fn close_dispute_with_reason(
+        &mut self,
+        tx_id: TransactionID,
+        reason: DisputeResolution,
+        reverse_funds: bool,
+    ) -> Result<(), BankError> {
+        let client = self
+            .disputes
+            .get(&tx_id)
+            .ok_or(BankError::DisputeNotFound(tx_id))?
+            .client();
+        let kind = if reverse_funds {
+            Kind::Chargeback
+        } else {
+            Kind::Resolve
+        };
+
+        let transaction = Transaction::new(kind, client, tx_id, None);
+        let result = self.submit(transaction, Origin::Bank, true, |entry| {
+            entry.with_resolution(reason)
+        });
+        if result.is_ok() {
+            // unwrap is safe because closing the dispute succeeded, so the account exists.
+            self.accounts
+                .get_mut(&client)
+                .unwrap()
+                .record_dispute_resolution(tx_id, reason);
+        }
+        result
+    }
+
+    /// Admin operation to resolve every open dispute, releasing all held funds back to available.
+    /// The resolves are recorded like any other transaction, but are applied even if the account
+    /// is frozen or over its velocity limit. Disputes which can not be resolved, e.g. because their
+    /// account no longer exists, are skipped and stay open.
+    pub fn force_resolve_all_disputes(&mut self) -> ForceResolveResult {
+        let mut disputes: Vec<(TransactionID, AccountID)> = self
+            .disputes
+            .values()
+            .map(|t| (t.id(), t.client()))
+            .collect();
+        disputes.sort_unstable();
+
+        let mut result = ForceResolveResult::default();
+        for (id, client) in disputes {
+            let resolve = Transaction::new(Kind::Resolve, client, id, None);
+            match self.submit(resolve, Origin::Bank, true, |entry| entry) {
+                Ok(()) => result.resolved.push(id),
+                Err(_) => result.skipped.push(id),
+            }
+        }
+        result
+    }
+
+    /// Admin operation for closing an account: charge back every open dispute of `client`, and lock
+    /// the account even if it had no open disputes. Returns the number of chargebacks applied. The
+    /// chargebacks are recorded like any other transaction, but are applied even if the account is
+    /// frozen or over its velocity limit.
+    pub fn force_chargeback_all_disputes_for_client(&mut self, client: AccountID) -> u32 {
+        let mut ids: Vec<TransactionID> = self
+            .disputes
+            .values()
+            .filter(|t| t.client() == client)
+            .map(Transaction::id)
+            .collect();
+        ids.sort_unstable();
+
+        let mut charged_back = 0;
+        for id in ids {
+            let chargeback = Transaction::new(Kind::Chargeback, client, id, None);
+            if self
+                .submit(chargeback, Origin::Bank, true, |entry| entry)
+                .is_ok()
+            {
+                charged_back += 1;
+            }
+        }
+        if let Some(account) = self.accounts.get_mut(&client) {
+            account.lock();
+        }
+
+        charged_back
+    }
+
+    // Deposits and withdrawals with new ids can only be recorded while there is room left.
+    fn check_storage(&self, tx_id: TransactionID) -> Result<(), BankError> {
+        match self.config.max_transactions {
+            Some(max)
+                if self.transactions.len() >= max && !self.transactions.contains_key(&tx_id) =>
+            {
+                Err(BankError::StorageFull)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Record a deposit or withdrawal so that it can later be disputed or reversed.
+    fn record_transaction(&mut self, transaction: Transaction) {
+        let (client, id) = (transaction.client(), transaction.id());
+        let replaced = self.transactions.insert(id, transaction);
+
+        if self.config.enable_client_index {
+            // A transaction with a reused id replaces the previous one, which may have belonged to
+            // a different client.
+            if let Some(replaced) = replaced {
+                if let Some(ids) = self.client_transactions.get_mut(&replaced.client()) {
+                    ids.retain(|t| *t != id);
+                }
+            }
+            self.client_transactions.entry(client).or_default().push(id);
+        }
+    }
+
+    // Remove an open dispute, along with its tracking data.
+    fn remove_dispute(&mut self, tx_id: TransactionID) -> Option<Transaction> {
+        self.dispute_opened_at.remove(&tx_id);
+        self.disputes.remove(&tx_id)
+    }
+
+    // TODO: Handle duplicate transactions id's
+    fn process_deposit(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        let amount = transaction.amount().ok_or(BankError::MissingAmount)?;
+        if amount.is_negative() {
+            return Err(BankError::NegativeAmount);
+        }
+        self.check_storage(transaction.id())?;
+        let account = self.accounts.entry(transaction.client()).or_default();
+
+        account.try_credit(amount)?;
+        self.net_flow = self.net_flow.wrapping_add(amount);
+        self.record_transaction(transaction);
+        Ok(())
+    }
+
+    fn process_withdrawl(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        let amount = transaction.amount().ok_or(BankError::MissingAmount)?;
+        if amount.is_negative() {
+            return Err(BankError::NegativeAmount);
+        }
+        self.check_storage(transaction.id())?;
+        let account = self
+            .accounts
+            .get_mut(&transaction.client())
+            .ok_or_else(|| BankError::AccountNotFound(transaction.client()))?;
+
+        // Same order as `Account::try_debit`, so a locked account is reported as such.
+        if account.is_frozen() {
+            return Err(AccountError::AccountFrozen.into());
+        }
+        if account.is_locked() {
+            return Err(AccountError::AccountLocked.into());
+        }
+        if let Some(limit) = account.withdrawal_limit() {
+            match account.available().checked_sub(amount) {
+                Some(available) if available >= -limit => {}
+                _ => return Err(BankError::ExceedsAccountLimit),
+            }
+        }
+        account.try_debit(amount)?;
+        self.net_flow = self.net_flow.wrapping_sub(amount);
+        self.record_transaction(transaction);
+        Ok(())
+    }
+
+    fn process_dispute(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        if self.disputes.contains_key(&transaction.id()) {
+            return Err(BankError::AlreadyDisputed(transaction.id()));
+        }
+        if self.reversals.contains(&transaction.id()) {
+            return Err(BankError::AlreadyReversed(transaction.id()));
+        }
+
+        let old_transaction = self
+            .transactions
+            .get(&transaction.id())
+            .ok_or_else(|| BankError::TransactionNotFound(transaction.id()))?;
+        if old_transaction.client() != transaction.client() {
+            return Err(BankError::ClientMismatch(transaction.id()));
+        }
+        if !matches!(old_transaction.kind(), Kind::Deposit) {
+            return Err(BankError::NotDisputable(transaction.id()));
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&old_transaction.client())
+            .ok_or_else(|| BankError::AccountNotFound(old_transaction.client()))?;
+
+        // unwrap is safe because we only would have inserted into `self.transactions` if there was
+        // a valid amount.
+        let amount = old_transaction.amount().unwrap();
+        account.try_dispute(amount)?;
+        self.disputes
+            .insert(transaction.id(), old_transaction.clone());
+        self.dispute_opened_at
+            .insert(transaction.id(), self.config.clock.now());
+        Ok(())
+    }
+
+    fn process_resolve(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        let disputed = self
+            .disputes
+            .get(&transaction.id())
+            .ok_or_else(|| BankError::DisputeNotFound(transaction.id()))?;
+        if disputed.client() != transaction.client() {
+            return Err(BankError::ClientMismatch(transaction.id()));
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&disputed.client())
+            .ok_or_else(|| BankError::AccountNotFound(disputed.client()))?;
+
+        // unwrap is safe because we only would have inserted into `self.disputes` if there was a
+        // valid amount.
+        account.try_resolve(disputed.amount().unwrap())?;
+        self.remove_dispute(transaction.id());
+        self.resolved.insert(transaction.id());
+        Ok(())
+    }
+
+    fn process_chargeback(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        let disputed = self
+            .disputes
+            .get(&transaction.id())
+            .ok_or_else(|| BankError::DisputeNotFound(transaction.id()))?;
+        if disputed.client() != transaction.client() {
+            return Err(BankError::ClientMismatch(transaction.id()));
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&disputed.client())
+            .ok_or_else(|| BankError::AccountNotFound(disputed.client()))?;
+
+        // unwrap is safe because we only would have inserted into `self.disputes` if there was a
+        // valid amount.
+        let amount = disputed.amount().unwrap();
+        account.try_chargeback(transaction.id(), amount)?;
+        self.net_flow = self.net_flow.wrapping_sub(amount);
+        self.remove_dispute(transaction.id());
+        Ok(())
+    }
+
+    fn process_admin_reversal(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        if self.reversals.contains(&transaction.id()) {
+            return Err(BankError::AlreadyReversed(transaction.id()));
+        }
+        if self.disputes.contains_key(&transaction.id()) {
+            return Err(BankError::AlreadyDisputed(transaction.id()));
+        }
+
+        let original = self
+            .transactions
+            .get(&transaction.id())
+            .ok_or_else(|| BankError::TransactionNotFound(transaction.id()))?;
+        if original.client() != transaction.client() {
+            return Err(BankError::ClientMismatch(transaction.id()));
+        }
+
+        let account = self
+            .accounts
+            .get_mut(&original.client())
+            .ok_or_else(|| BankError::AccountNotFound(original.client()))?;
+
+        // unwrap is safe because we only would have inserted into `self.transactions` if there was
+        // a valid amount.
+        let amount = original.amount().unwrap();
+        match original.kind() {
+            Kind::Deposit => {
+                account.try_debit(amount)?;
+                self.net_flow = self.net_flow.wrapping_sub(amount);
+            }
+            Kind::Withdrawal => {
+                account.try_credit(amount)?;
+                self.net_flow = self.net_flow.wrapping_add(amount);
+            }
+            _ => unreachable!("only deposits and withdrawals are recorded"),
+        }
+
+        account.flag_for_review();
+        self.reversals.insert(transaction.id());
+        Ok(())
+    }
+
+    fn process_adjustment(&mut self, transaction: Transaction) -> Result<(), BankError> {
+        if !self.config.allow_adjustments {
+            return Err(BankError::AdjustmentsDisabled);
+        }
+        let delta = transaction.amount().ok_or(BankError::MissingAmount)?;
+        let account = self
+            .accounts
+            .get_mut(&transaction.client())
+            .ok_or_else(|| BankError::AccountNotFound(transaction.client()))?;
+
+        account.try_adjust(delta)?;
+        self.net_flow = self.net_flow.wrapping_add(delta);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use crate::{clock::MockClock, config::ReviewThresholds};
+
+    #[test]
+    fn iterator() {
+        let mut account = Account::new();
+        account.try_credit(10000.into()).unwrap();
+
+        let mut dispute_account = Account::new();
+        dispute_account.try_credit(10000.into()).unwrap();
+        dispute_account.try_dispute(5000.into()).unwrap();
+
+        let mut frozen_account = Account::new();
+        frozen_account.try_credit(10000.into()).unwrap();
+        frozen_account.try_dispute(5000.into()).unwrap();
+        frozen_account.try_chargeback(3, 5000.into()).unwrap();
+
+        let mut accounts = HashMap::new();
+        accounts.insert(1, account);
+        accounts.insert(2, dispute_account);
+        accounts.insert(3, frozen_account);
+
+        let bank = Bank {
+            accounts,
+            transactions: HashMap::new(),
+            disputes: HashMap::new(),
+            ..Bank::new()
+        };
+
+        let mut accounts: Vec<(u16, Amount, Amount, Amount, bool)> = bank.accounts_iter().collect();
+        accounts.sort_by_key(|x| x.0);
+
+        assert_eq!(
+            accounts,
+            vec![
+                (1, 1.0.into(), 0.0.into(), 1.0.into(), false),
+                (2, 0.5.into(), 0.5.into(), 1.0.into(), false),
+                (3, 0.5.into(), 0.0.into(), 0.5.into(), true),
+            ]
+        );
+        for (id, available, held, total, locked) in accounts {
+            assert_eq!(
+                bank.get_account(id).unwrap().as_balance_tuple(),
+                (available, held, total, locked)
+            );
+        }
+    }
+
+    #[test]
+    fn iterator_sorted_by_id() {
+        let mut bank = Bank::new();
+        for client in [7, 3, 42, 1, 9] {
+            bank.process_transaction(Transaction::new(
+                Kind::Deposit,
+                client,
+                client.into(),
+                Some(1.0.into()),
+            ))
+            .unwrap();
+        }
+
+        let ids: Vec<AccountID> = bank
+            .accounts_iter_sorted_by_id()
+            .map(|(id, ..)| id)
+            .collect();
+        assert_eq!(ids, vec![1, 3, 7, 9, 42]);
+    }
+
+    #[test]
+    fn with_accounts() {
+        let mut account = Account::new();
+        account.try_credit(5.0.into()).unwrap();
+
+        let mut bank = Bank::with_accounts(vec![(1, account), (2, Account::new())]);
+        assert!(bank.transactions.is_empty());
+        assert!(bank.disputes.is_empty());
+
+        bank.process_transaction(Transaction::new(Kind::Withdrawal, 1, 1, Some(2.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 2, 2, Some(1.0.into())))
+            .unwrap();
+
+        let accounts: Vec<(u16, Amount, Amount, Amount, bool)> =
+            bank.accounts_iter_sorted_by_id().collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (1, 3.0.into(), 0.0.into(), 3.0.into(), false),
+                (2, 1.0.into(), 0.0.into(), 1.0.into(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn sub_accounts() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(3, 2, 99.0.into()).unwrap();
+
+        let children: Vec<AccountID> = (0..3)
+            .map(|_| bank.create_sub_account(1).unwrap())
+            .collect();
+        assert_eq!(children, vec![0, 2, 4]);
+
+        for (tx, child) in (3..).zip(&children) {
+            assert_eq!(bank.accounts[child].parent_id(), Some(1));
+            bank.process_deposit_raw(*child, tx, 2.0.into()).unwrap();
+        }
+
+        assert_eq!(bank.get_group_balance(1), 16.0.into());
+        assert_eq!(bank.get_group_balance(3), 99.0.into());
+        assert_eq!(
+            bank.create_sub_account(5),
+            Err(BankError::AccountNotFound(5))
+        );
+
+        bank.process_deposit_raw(1, 6, 900_000_000_000_000.0.into())
+            .unwrap();
+        bank.process_deposit_raw(children[0], 7, 900_000_000_000_000.0.into())
+            .unwrap();
+        assert_eq!(bank.get_group_balance(1), Amount::MAX);
+    }
+
+    #[test]
+    fn accounts_by_group() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(10, 1, 1.0.into()).unwrap();
+        bank.process_deposit_raw(20, 2, 1.0.into()).unwrap();
+        bank.process_deposit_raw(30, 3, 1.0.into()).unwrap();
+
+        let a = bank.create_sub_account(10).unwrap();
+        let b = bank.create_sub_account(10).unwrap();
+        let c = bank.create_sub_account(20).unwrap();
+
+        let groups = bank.accounts_by_group();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&None], vec![10, 20, 30]);
+        assert_eq!(groups[&Some(10)], vec![a, b]);
+        assert_eq!(groups[&Some(20)], vec![c]);
+    }
+
+    #[test]
+    fn merge_accounts() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 4.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+        let child = bank.create_sub_account(1).unwrap();
+
+        bank.merge_accounts(1, 2).unwrap();
+        assert!(!bank.accounts.contains_key(&1));
+        assert_eq!(bank.accounts[&2].available(), 11.0.into());
+        let statement: Vec<_> = bank.account_statement(2).iter().map(|e| e.tx_id).collect();
+        assert_eq!(statement, vec![3, 1, 2]);
+        assert!(bank.account_statement(1).is_empty());
+        assert_eq!(bank.transaction_velocity(1, 10), 0);
+        assert_eq!(bank.transaction_velocity(2, 10), 3);
+        assert_eq!(bank.accounts[&child].parent_id(), Some(2));
+        assert!(bank.transactions.values().all(|t| t.client() == 2));
+
+        // The re-pointed deposit can now be disputed by its new owner.
+        bank.process_dispute_raw(2, 1).unwrap();
+        assert_eq!(bank.accounts[&2].held(), 10.0.into());
+    }
+
+    #[test]
+    fn merge_overdrawn_account() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.set_account_limit(1, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 12.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+
+        bank.merge_accounts(1, 2).unwrap();
+        assert_eq!(bank.accounts[&2].available(), 3.0.into());
+        assert_eq!(bank.reconcile(), Ok(()));
+    }
+
+    #[test]
+    fn merge_accounts_with_open_disputes() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 1.0.into()).unwrap();
+
+        let before = bank.clone();
+        assert_eq!(bank.merge_accounts(1, 2), Err(BankError::OpenDisputes(1)));
+        assert_eq!(bank.merge_accounts(2, 2), Err(BankError::SameAccount(2)));
+        assert_eq!(
+            bank.merge_accounts(2, 3),
+            Err(BankError::AccountNotFound(3))
+        );
+        assert_eq!(before.diff(&bank), BankDiff::default());
+        assert_eq!(bank.accounts[&2].available(), 1.0.into());
+    }
+
+    #[test]
+    fn rekey_account() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 1.0.into()).unwrap();
+        bank.process_deposit_raw(2, 4, 7.0.into()).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        let child = bank.create_sub_account(1).unwrap();
+
+        assert_eq!(
+            bank.rekey_account(9, 10),
+            Err(BankError::AccountNotFound(9))
+        );
+        assert_eq!(
+            bank.rekey_account(1, 2),
+            Err(BankError::DuplicateAccountId(2))
+        );
+
+        bank.rekey_account(1, 42).unwrap();
+        assert!(!bank.accounts.contains_key(&1));
+        assert_eq!(bank.accounts[&42].available(), 9.0.into());
+        assert_eq!(bank.accounts[&42].held(), 5.0.into());
+        assert_eq!(bank.accounts[&child].parent_id(), Some(42));
+        for id in [1, 2, 3] {
+            assert_eq!(bank.transactions[&id].client(), 42);
+        }
+        assert_eq!(bank.transactions[&4].client(), 2);
+        assert_eq!(bank.disputes[&2].client(), 42);
+        assert_eq!(bank.account_statement(42).len(), 4);
+        assert_eq!(bank.validate_consistency(), Ok(()));
+
+        // The moved deposits can only be used through the new id.
+        assert_eq!(
+            bank.process_resolve_raw(1, 2),
+            Err(BankError::ClientMismatch(2))
+        );
+        bank.process_resolve_raw(42, 2).unwrap();
+        bank.process_dispute_raw(42, 1).unwrap();
+        assert_eq!(bank.accounts[&42].held(), 10.0.into());
+    }
+
+    fn disputed_bank() -> Bank {
+        let mut bank = Bank::new();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Dispute, 1, 1, None))
+            .unwrap();
+        bank
+    }
+
+    #[test]
+    fn resolve_partial_full_amount() {
+        let mut bank = disputed_bank();
+        bank.process_resolve_partial(1, 10.0.into()).unwrap();
+
+        let account = &bank.accounts[&1];
+        assert_eq!(account.held(), 0.into());
+        assert_eq!(account.available(), 10.0.into());
+        assert!(bank.disputes.is_empty());
+    }
+
+    #[test]
+    fn resolve_partial_half_amount() {
+        let mut bank = disputed_bank();
+        bank.process_resolve_partial(1, 5.0.into()).unwrap();
+
+        let account = &bank.accounts[&1];
+        assert_eq!(account.held(), 5.0.into());
+        assert_eq!(account.available(), 5.0.into());
+        assert_eq!(bank.disputes[&1].amount(), Some(5.0.into()));
+
+        // The remainder can still be resolved as usual.
+        bank.process_transaction(Transaction::new(Kind::Resolve, 1, 1, None))
+            .unwrap();
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+        assert!(bank.disputes.is_empty());
+    }
+
+    #[test]
+    fn resolve_partial_over_amount() {
+        let mut bank = disputed_bank();
+        assert_eq!(
+            bank.process_resolve_partial(1, 11.0.into()),
+            Err(BankError::ExceedsDisputedAmount)
+        );
+        assert_eq!(
+            bank.process_resolve_partial(2, 1.0.into()),
+            Err(BankError::DisputeNotFound(2))
+        );
+        assert_eq!(bank.accounts[&1].held(), 10.0.into());
+    }
+
+    #[test]
+    fn resolve_partial_non_positive_amount() {
+        let mut bank = disputed_bank();
+        let handler = MockHandler::default();
+        bank.subscribe(Box::new(handler.clone()));
+
+        assert_eq!(
+            bank.process_resolve_partial(1, (-5.0).into()),
+            Err(BankError::NegativeAmount)
+        );
+        assert_eq!(
+            bank.process_resolve_partial(1, Amount::new()),
+            Err(AccountError::NonPositiveAmount.into())
+        );
+        let account = &bank.accounts[&1];
+        assert_eq!(account.held(), 10.0.into());
+        assert_eq!(account.available(), 0.into());
+        assert_eq!(bank.disputes[&1].amount(), Some(10.0.into()));
+        assert!(handler.events().is_empty());
+    }
+
+    #[test]
+    fn resolve_partial_records_audit_and_statement() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+
+        bank.process_resolve_partial(1, 4.0.into()).unwrap();
+        bank.process_resolve_partial(1, 20.0.into()).unwrap_err();
+        bank.process_resolve_partial(1, 6.0.into()).unwrap();
+
+        let trail = bank.audit_trail();
+        assert_eq!(trail.len(), 5);
+        assert_eq!(trail[2].transaction().kind(), Kind::Resolve);
+        assert_eq!(trail[2].transaction().amount(), Some(4.0.into()));
+        assert!(trail[3].outcome().is_err());
+        let statement: Vec<_> = bank
+            .account_statement(1)
+            .iter()
+            .map(|e| (e.kind, e.amount))
+            .collect();
+        assert_eq!(
+            statement,
+            vec![
+                (Kind::Deposit, Some(10.0.into())),
+                (Kind::Dispute, None),
+                (Kind::Resolve, Some(4.0.into())),
+                (Kind::Resolve, Some(6.0.into())),
+            ]
+        );
+        assert_eq!(bank.account_statement(1)[2].balance_after, 10.0.into());
+
+        let replayed = Bank::replay_from_audit_trail(&trail[..3], BankConfig::default()).unwrap();
+        assert_eq!(replayed.accounts[&1].held(), 6.0.into());
+        assert_eq!(replayed.disputes[&1].amount(), Some(6.0.into()));
+    }
+
+    #[test]
+    fn force_resolve_all_disputes() {
+        let mut bank = disputed_bank();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 2, 2, Some(3.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 2, 3, Some(4.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Dispute, 2, 2, None))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Dispute, 2, 3, None))
+            .unwrap();
+
+        // Disputes whose account has been removed, or whose funds are not held, are skipped.
+        bank.disputes
+            .insert(4, Transaction::new(Kind::Deposit, 3, 4, Some(1.0.into())));
+        bank.disputes
+            .insert(5, Transaction::new(Kind::Deposit, 2, 5, Some(100.0.into())));
+
+        assert_eq!(
+            bank.force_resolve_all_disputes(),
+            ForceResolveResult {
+                resolved: vec![1, 2, 3],
+                skipped: vec![4, 5],
+            }
+        );
+        let mut open: Vec<_> = bank.disputes.keys().copied().collect();
+        open.sort_unstable();
+        assert_eq!(open, vec![4, 5]);
+
+        assert_eq!(bank.accounts[&1].held(), 0.into());
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+        assert_eq!(bank.accounts[&2].held(), 0.into());
+        assert_eq!(bank.accounts[&2].available(), 7.0.into());
+    }
+
+    #[test]
+    fn force_resolve_all_disputes_for_frozen_account() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.freeze_account(1).unwrap();
+
+        assert_eq!(
+            bank.force_resolve_all_disputes(),
+            ForceResolveResult {
+                resolved: vec![1],
+                skipped: vec![],
+            }
+        );
+        assert!(bank.disputes.is_empty());
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+        assert_eq!(bank.accounts[&1].transaction_count(), 2);
+        assert_eq!(
+            bank.account_statement(1).last().unwrap().kind,
+            Kind::Resolve
+        );
+
+        let entry = bank.audit_trail().last().unwrap();
+        assert_eq!(entry.transaction().kind(), Kind::Resolve);
+        assert!(entry.outcome().is_ok());
+        let replayed =
+            Bank::replay_from_audit_trail(bank.audit_trail(), bank.config().clone()).unwrap();
+        assert_eq!(replayed.accounts[&1].available(), 10.0.into());
+        assert!(replayed.disputes.is_empty());
+    }
+
+    #[test]
+    fn count_disputes_per_account() {
+        let mut bank = disputed_bank();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 2, 2, Some(3.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 2, 3, Some(4.0.into())))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Dispute, 2, 2, None))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Dispute, 2, 3, None))
+            .unwrap();
+        bank.process_transaction(Transaction::new(Kind::Deposit, 3, 4, Some(1.0.into())))
+            .unwrap();
+
+        let counts = bank.count_disputes_per_account();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&1], 1);
+        assert_eq!(counts[&2], 2);
+        assert!(!counts.contains_key(&3));
+    }
+
+    #[test]
+    fn deposit_raw() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        assert_eq!(
+            bank.process_deposit_raw(1, 2, 5.0.into()),
+            Err(BankError::Account(AccountError::AccountLocked))
+        );
+        assert!(!bank.transactions.contains_key(&2));
+
+        assert_eq!(
+            bank.process_deposit_raw(2, 3, (-1.0).into()),
+            Err(BankError::NegativeAmount)
+        );
+        assert!(!bank.accounts.contains_key(&2));
+    }
+
+    #[test]
+    fn withdrawal_raw() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 2.0.into()).unwrap();
+        assert_eq!(bank.accounts[&1].available(), 3.0.into());
+
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 3, 4.0.into()),
+            Err(BankError::Account(AccountError::InsufficientFunds))
+        );
+        assert_eq!(
+            bank.process_withdrawal_raw(2, 4, 1.0.into()),
+            Err(BankError::AccountNotFound(2))
+        );
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 5, (-1.0).into()),
+            Err(BankError::NegativeAmount)
+        );
+        assert_eq!(bank.accounts[&1].available(), 3.0.into());
+    }
+
+    #[test]
+    fn dispute_raw() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        assert_eq!(bank.accounts[&1].held(), 5.0.into());
+
+        assert_eq!(
+            bank.process_dispute_raw(1, 1),
+            Err(BankError::AlreadyDisputed(1))
+        );
+        assert_eq!(
+            bank.process_dispute_raw(1, 2),
+            Err(BankError::TransactionNotFound(2))
+        );
+
+        bank.process_deposit_raw(2, 3, 1.0.into()).unwrap();
+        assert_eq!(
+            bank.process_dispute_raw(1, 3),
+            Err(BankError::ClientMismatch(3))
+        );
+        assert_eq!(bank.accounts[&1].held(), 5.0.into());
+    }
+
+    #[test]
+    fn resolve_raw() {
+        let mut bank = disputed_bank();
+        assert_eq!(
+            bank.process_resolve_raw(2, 1),
+            Err(BankError::ClientMismatch(1))
+        );
+        bank.process_resolve_raw(1, 1).unwrap();
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+        assert_eq!(
+            bank.process_resolve_raw(1, 1),
+            Err(BankError::DisputeNotFound(1))
+        );
+    }
+
+    #[test]
+    fn chargeback_raw() {
+        let mut bank = disputed_bank();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        assert_eq!(bank.accounts[&1].total(), 0.into());
+        assert!(bank.accounts[&1].is_locked());
+        assert_eq!(bank.accounts[&1].chargeback_history(), &[(1, 10.0.into())]);
+        assert_eq!(
+            bank.process_chargeback_raw(1, 1),
+            Err(BankError::DisputeNotFound(1))
+        );
+    }
+
+    #[test]
+    fn accounts_needing_review() {
+        let mut bank = Bank::with_config(BankConfig {
+            review_thresholds: ReviewThresholds {
+                max_open_disputes: 1,
+                max_held: 100.0.into(),
+            },
+            ..BankConfig::default()
+        });
+
+        // A clean account is never flagged.
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+
+        // Locked by a chargeback.
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.process_dispute_raw(2, 2).unwrap();
+        bank.process_chargeback_raw(2, 2).unwrap();
+
+        // Two open disputes, above the maximum of one.
+        bank.process_deposit_raw(3, 3, 10.0.into()).unwrap();
+        bank.process_deposit_raw(3, 4, 10.0.into()).unwrap();
+        bank.process_dispute_raw(3, 3).unwrap();
+        bank.process_dispute_raw(3, 4).unwrap();
+
+        // Holding more than the maximum.
+        bank.process_deposit_raw(4, 5, 150.0.into()).unwrap();
+        bank.process_dispute_raw(4, 5).unwrap();
+
+        // Negative balances can not be reached through transactions without an account limit.
+        bank.accounts.insert(
+            5,
+            Account::with_balances((-1.0).into(), Amount::new(), false),
+        );
+
+        let mut flagged: Vec<(AccountID, Vec<ReviewFlag>)> =
+            bank.accounts_needing_review().collect();
+        flagged.sort_by_key(|(id, _)| *id);
+
+        assert_eq!(
+            flagged,
+            vec![
+                (2, vec![ReviewFlag::Locked]),
+                (3, vec![ReviewFlag::ExcessiveDisputes(2)]),
+                (4, vec![ReviewFlag::LargeHeld(150.0.into())]),
+                (5, vec![ReviewFlag::NegativeBalance]),
+            ]
+        );
+    }
+
+    #[test]
+    fn chargeback_exposure() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 2.5.into()).unwrap();
+        assert_eq!(bank.chargeback_exposure(), 0.into());
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(2, 3).unwrap();
+        assert_eq!(bank.chargeback_exposure(), 12.5.into());
+        assert_eq!(bank.total_held(), 12.5.into());
+
+        bank.process_resolve_raw(1, 1).unwrap();
+        assert_eq!(bank.chargeback_exposure(), 2.5.into());
+
+        bank.process_chargeback_raw(2, 3).unwrap();
+        assert_eq!(bank.chargeback_exposure(), 0.into());
+        assert_eq!(bank.total_held(), 0.into());
+    }
+
+    #[test]
+    fn totals_saturate() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 900_000_000_000_000.0.into())
+            .unwrap();
+        bank.process_deposit_raw(2, 2, 900_000_000_000_000.0.into())
+            .unwrap();
+        assert_eq!(bank.total_assets(), Amount::MAX);
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(2, 2).unwrap();
+        assert_eq!(bank.total_held(), Amount::MAX);
+        bank.write_summary(std::io::sink()).unwrap();
+    }
+
+    #[test]
+    fn dispute_withdrawal() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 1.0.into()).unwrap();
+        assert_eq!(
+            bank.process_dispute_raw(1, 2),
+            Err(BankError::NotDisputable(2))
+        );
+        assert_eq!(bank.accounts[&1].held(), 0.into());
+    }
+
+    #[test]
+    fn admin_reversal() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 1.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+
+        bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 2, None))
+            .unwrap();
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+        assert!(bank.accounts[&1].is_flagged_for_review());
+        assert!(!bank.accounts[&2].is_flagged_for_review());
+
+        bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 1, None))
+            .unwrap();
+        assert_eq!(bank.accounts[&1].available(), 0.into());
+
+        // A reversed deposit can no longer be disputed.
+        assert_eq!(
+            bank.process_dispute_raw(1, 1),
+            Err(BankError::AlreadyReversed(1))
+        );
+    }
+
+    #[test]
+    fn admin_reversal_not_found() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        assert_eq!(
+            bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 2, None)),
+            Err(BankError::TransactionNotFound(2))
+        );
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+        assert!(!bank.accounts[&1].is_flagged_for_review());
+    }
+
+    #[test]
+    fn admin_reversal_twice() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 1, None))
+            .unwrap();
+        assert_eq!(
+            bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 1, None)),
+            Err(BankError::AlreadyReversed(1))
+        );
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn prometheus_metrics() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 2.5.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 1.0.into()).unwrap();
+        bank.process_dispute_raw(3, 3).unwrap();
+        bank.process_chargeback_raw(3, 3).unwrap();
+
+        let mut output = Vec::new();
+        bank.prometheus_metrics(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let gauges: HashMap<&str, f64> = output
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, value) = line.split_once(' ').unwrap();
+                (name, value.parse().unwrap())
+            })
+            .collect();
+
+        assert_eq!(gauges.len(), 5);
+        assert_eq!(gauges["banker_accounts_total"], 3.0);
+        assert_eq!(gauges["banker_locked_accounts_total"], 1.0);
+        assert_eq!(gauges["banker_open_disputes_total"], 1.0);
+        assert_eq!(gauges["banker_total_assets"], 12.5);
+        assert_eq!(gauges["banker_total_held"], 10.0);
+    }
+
+    #[test]
+    fn transaction_volume() {
+        let mut bank = Bank::with_config(BankConfig {
+            recent_transactions: 4,
+            ..BankConfig::default()
+        });
+        assert_eq!(bank.transaction_volume(3), 0.into());
+
+        for tx in 1..=5 {
+            bank.process_deposit_raw(1, tx, 100.0.into()).unwrap();
+        }
+        assert_eq!(bank.transaction_volume(3), 300.0.into());
+        assert_eq!(bank.transaction_volume(10), 400.0.into());
+
+        // Failed transactions are not recorded.
+        bank.process_withdrawal_raw(1, 6, 1000.0.into())
+            .unwrap_err();
+        bank.process_withdrawal_raw(1, 7, 50.0.into()).unwrap();
+        assert_eq!(bank.transaction_volume(2), 150.0.into());
+    }
+
+    #[test]
+    fn transaction_velocity() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.transaction_velocity(1, 10), 0);
+
+        for tx in 1..=5 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        for tx in 6..=10 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+            bank.process_deposit_raw(2, tx + 10, 1.0.into()).unwrap();
+        }
+        // Failed transactions are not counted.
+        bank.process_withdrawal_raw(1, 21, 100.0.into())
+            .unwrap_err();
+
+        assert_eq!(bank.transaction_velocity(1, 10), 5);
+        assert_eq!(bank.transaction_velocity(2, 10), 5);
+        assert_eq!(bank.transaction_velocity(1, 3), 1);
+        assert_eq!(bank.transaction_velocity(1, 100), 10);
+        assert_eq!(bank.transaction_velocity(3, 100), 0);
+    }
+
+    #[test]
+    fn max_velocity() {
+        let mut bank = Bank::with_config(BankConfig {
+            max_velocity: Some((10, 20)),
+            ..BankConfig::default()
+        });
+        for tx in 1..=10 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        assert_eq!(
+            bank.process_deposit_raw(1, 11, 1.0.into()),
+            Err(BankError::VelocityLimitExceeded(1))
+        );
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+
+        // Other clients are not affected, and push the earlier transactions out of the window.
+        for tx in 12..=21 {
+            bank.process_deposit_raw(2, tx, 1.0.into()).unwrap();
+        }
+        bank.process_deposit_raw(1, 11, 1.0.into()).unwrap_err();
+        bank.process_deposit_raw(2, 22, 1.0.into()).unwrap_err();
+        bank.process_deposit_raw(3, 23, 1.0.into()).unwrap();
+        bank.process_deposit_raw(1, 11, 1.0.into()).unwrap();
+    }
+
+    #[test]
+    fn process_transaction_checked() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            track_kinds_seen: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+        bank.process_dispute_raw(2, 2).unwrap();
+        bank.process_chargeback_raw(2, 2).unwrap();
+        bank.process_resolve_raw(2, 2).unwrap_err();
+
+        let failing = [
+            (
+                Transaction::new(Kind::Deposit, 1, 3, None),
+                BankError::MissingAmount,
+            ),
+            (
+                Transaction::new(Kind::Withdrawal, 1, 3, Some((-1.0).into())),
+                BankError::NegativeAmount,
+            ),
+            (
+                Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())),
+                BankError::DuplicateTransaction(1),
+            ),
+            (
+                Transaction::new(Kind::Deposit, 2, 3, Some(1.0.into())),
+                AccountError::AccountLocked.into(),
+            ),
+            (
+                Transaction::new(Kind::Withdrawal, 1, 3, Some(11.0.into())),
+                AccountError::InsufficientFunds.into(),
+            ),
+            (
+                Transaction::new(Kind::Withdrawal, 3, 3, Some(1.0.into())),
+                BankError::AccountNotFound(3),
+            ),
+            (
+                Transaction::new(Kind::Resolve, 1, 1, None),
+                BankError::DisputeNotFound(1),
+            ),
+        ];
+        for (transaction, error) in failing {
+            let before = bank.clone();
+            assert_eq!(bank.process_transaction_checked(transaction), Err(error));
+            assert_eq!(before.diff(&bank), BankDiff::default());
+            assert_eq!(bank.audit_trail().len(), before.audit_trail().len());
+            assert_eq!(bank.account_statement(1), before.account_statement(1));
+            assert_eq!(bank.transaction_volume(100), before.transaction_volume(100));
+            assert_eq!(
+                bank.transaction_error_rate(10),
+                before.transaction_error_rate(10)
+            );
+            assert_eq!(bank.list_all_kinds_seen(), before.list_all_kinds_seen());
+        }
+        assert_eq!(bank.transaction_error_rate(10), 0.2);
+
+        let withdrawal = Transaction::new(Kind::Withdrawal, 1, 3, Some(1.0.into()));
+        bank.process_transaction_checked(withdrawal).unwrap();
+        assert_eq!(bank.accounts[&1].available(), 9.0.into());
+        assert!(bank.audit_trail().last().unwrap().outcome().is_ok());
+        assert_eq!(bank.transaction_error_rate(1), 0.0);
+        assert_eq!(bank.transaction_error_rate(10), 1.0 / 6.0);
+        assert!(bank.list_all_kinds_seen().contains(&Kind::Withdrawal));
+    }
+
+    #[test]
+    fn transaction_error_rate() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.transaction_error_rate(10), 0.0);
+
+        for tx in 1..=7 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        bank.process_withdrawal_raw(1, 8, 100.0.into()).unwrap_err();
+        bank.process_dispute_raw(1, 100).unwrap_err();
+        bank.process_withdrawal_raw(2, 9, 1.0.into()).unwrap_err();
+
+        assert_eq!(bank.transaction_error_rate(10), 0.3);
+        assert_eq!(bank.transaction_error_rate(2), 1.0);
+        assert_eq!(bank.transaction_error_rate(5), 0.6);
+        assert_eq!(bank.transaction_error_rate(100), 0.3);
+    }
+
+    #[test]
+    fn reset_error_rate_counters() {
+        let mut bank = Bank::with_config(BankConfig {
+            recent_transactions: 5,
+            ..BankConfig::default()
+        });
+        for tx in 1..=10 {
+            bank.process_withdrawal_raw(1, tx, 1.0.into()).unwrap_err();
+        }
+        assert_eq!(bank.transaction_error_rate(5), 1.0);
+
+        bank.reset_error_rate_counters();
+        assert_eq!(bank.transaction_error_rate(5), 0.0);
+
+        bank.process_deposit_raw(1, 11, 1.0.into()).unwrap();
+        assert_eq!(bank.transaction_error_rate(5), 0.0);
+    }
+
+    #[test]
+    fn replay_from_audit_trail() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+
+        let transactions = vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())),
+            Transaction::new(Kind::Deposit, 2, 2, Some(20.0.into())),
+            Transaction::new(Kind::Deposit, 3, 3, Some(30.0.into())),
+            Transaction::new(Kind::Withdrawal, 1, 4, Some(5.0.into())),
+            Transaction::new(Kind::Withdrawal, 2, 5, Some(50.0.into())),
+            Transaction::new(Kind::Dispute, 2, 2, None),
+            Transaction::new(Kind::Dispute, 2, 2, None),
+            Transaction::new(Kind::Resolve, 2, 2, None),
+            Transaction::new(Kind::Dispute, 3, 3, None),
+            Transaction::new(Kind::Chargeback, 3, 3, None),
+            Transaction::new(Kind::Deposit, 3, 6, Some(1.0.into())),
+            Transaction::new(Kind::Deposit, 4, 7, Some(4.0.into())),
+            Transaction::new(Kind::Withdrawal, 5, 8, Some(1.0.into())),
+            Transaction::new(Kind::Dispute, 1, 9, None),
+            Transaction::new(Kind::Deposit, 1, 10, Some(2.5.into())),
+            Transaction::new(Kind::Dispute, 1, 10, None),
+            Transaction::new(Kind::Withdrawal, 4, 11, Some(1.0.into())),
+            Transaction::new(Kind::AdminReversal, 4, 11, None),
+            Transaction::new(Kind::Resolve, 4, 7, None),
+            Transaction::new(Kind::Deposit, 2, 12, Some(0.5.into())),
+        ];
+        for transaction in transactions {
+            let _ = bank.process_transaction(transaction);
+        }
+
+        let trail = bank.audit_trail().to_vec();
+        assert_eq!(trail.len(), 20);
+        assert_eq!(trail.iter().filter(|e| e.outcome().is_err()).count(), 6);
+
+        let replayed = Bank::replay_from_audit_trail(&trail, BankConfig::default()).unwrap();
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+        assert_eq!(replayed.diff(&bank), BankDiff::default());
+        assert_eq!(replayed.disputes.len(), 1);
+    }
+
+    #[test]
+    fn replay_from_audit_trail_with_config() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            allow_adjustments: true,
+            max_velocity: Some((2, 3)),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_transaction(Transaction::new(Kind::Adjustment, 1, 2, Some(5.0.into())))
+            .unwrap();
+        assert_eq!(
+            bank.process_deposit_raw(1, 3, 1.0.into()),
+            Err(BankError::VelocityLimitExceeded(1))
+        );
+        bank.process_deposit_raw(2, 4, 1.0.into()).unwrap();
+
+        let trail = bank.audit_trail();
+        let replayed = Bank::replay_from_audit_trail(trail, bank.config().clone()).unwrap();
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+        assert_eq!(
+            Bank::replay_from_audit_trail(trail, BankConfig::default()).err(),
+            Some(BankError::AdjustmentsDisabled)
+        );
+    }
+
+    #[test]
+    fn events_since() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        assert!(bank.events_since(0).is_empty());
+
+        let mut checkpoint = 0;
+        for (cycle, count) in [(0, 3), (1, 1), (2, 0), (3, 2)] {
+            for i in 0..count {
+                let tx = cycle * 10 + i;
+                let _ = bank.process_deposit_raw(1, tx, 1.0.into());
+            }
+
+            let events = bank.events_since(checkpoint);
+            assert_eq!(events.len(), count as usize);
+            for (i, entry) in events.iter().enumerate() {
+                assert_eq!(entry.transaction().id(), cycle * 10 + i as u32);
+            }
+            checkpoint += events.len();
+        }
+
+        assert_eq!(checkpoint, 6);
+        assert_eq!(bank.events_since(2).len(), 4);
+        assert!(bank.events_since(100).is_empty());
+    }
+
+    // Simulates an external log store which reads new audit trail entries after each batch.
+    fn consume_new_events(bank: &Bank, cursor: &mut usize, log: &mut Vec<TransactionID>) {
+        log.extend(
+            bank.events_since(*cursor)
+                .iter()
+                .map(|e| e.transaction().id()),
+        );
+        *cursor = bank.checkpoint_audit_trail();
+    }
+
+    #[test]
+    fn checkpoint_audit_trail() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        assert_eq!(bank.checkpoint_audit_trail(), 0);
+
+        let mut cursor = bank.checkpoint_audit_trail();
+        let mut log = Vec::new();
+
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 10.0.into()).unwrap();
+        consume_new_events(&bank, &mut cursor, &mut log);
+        assert_eq!(cursor, 2);
+        assert_eq!(log, vec![1, 2]);
+
+        consume_new_events(&bank, &mut cursor, &mut log);
+        assert_eq!(cursor, 2);
+        assert_eq!(log, vec![1, 2]);
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        let _ = bank.process_withdrawal_raw(1, 3, 100.0.into());
+        consume_new_events(&bank, &mut cursor, &mut log);
+        assert_eq!(cursor, 4);
+        assert_eq!(log, vec![1, 2, 1, 3]);
+        assert_eq!(log.len(), bank.audit_trail().len());
+    }
+
+    #[test]
+    fn process_transaction_with_metadata() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        let metadata = HashMap::from([
+            ("delivery_id".to_string(), "abc-123".to_string()),
+            ("operator".to_string(), "alice".to_string()),
+        ]);
+        bank.process_transaction_with_metadata(
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())),
+            metadata.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            bank.process_transaction_with_metadata(
+                Transaction::new(Kind::Withdrawal, 1, 2, Some(40.0.into())),
+                HashMap::from([("source".to_string(), "webhook".to_string())]),
+            ),
+            Err(BankError::Account(AccountError::InsufficientFunds))
+        );
+        bank.process_withdrawal_raw(1, 3, 1.0.into()).unwrap();
+
+        let trail = bank.audit_trail();
+        assert_eq!(trail[0].metadata(), &metadata);
+        assert_eq!(trail[1].metadata()["source"], "webhook");
+        assert!(trail[1].outcome().is_err());
+        assert!(trail[2].metadata().is_empty());
+    }
+
+    #[test]
+    fn process_transaction_dry_run() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        let accounts = bank.accounts.clone();
+
+        let withdrawal = Transaction::new(Kind::Withdrawal, 1, 2, Some(4.0.into()));
+        let result = bank.process_transaction_dry_run(&withdrawal).unwrap();
+        assert!(result.would_succeed);
+        assert_eq!(result.new_available, 6.0.into());
+
+        let withdrawal = Transaction::new(Kind::Withdrawal, 1, 3, Some(40.0.into()));
+        assert!(
+            !bank
+                .process_transaction_dry_run(&withdrawal)
+                .unwrap()
+                .would_succeed
+        );
+
+        let deposit = Transaction::new(Kind::Deposit, 2, 4, Some(1.0.into()));
+        assert!(
+            bank.process_transaction_dry_run(&deposit)
+                .unwrap()
+                .would_succeed
+        );
+
+        let trail = bank.audit_trail();
+        assert_eq!(trail.len(), 4);
+        assert!(!trail[0].is_dry_run());
+        assert_eq!(
+            trail[1..]
+                .iter()
+                .map(AuditEntry::outcome)
+                .collect::<Vec<_>>(),
+            vec![
+                &AuditOutcome::DryRun {
+                    would_succeed: true
+                },
+                &AuditOutcome::DryRun {
+                    would_succeed: false
+                },
+                &AuditOutcome::DryRun {
+                    would_succeed: true
+                },
+            ]
+        );
+
+        assert_eq!(bank.accounts, accounts);
+        assert!(!bank.transactions.contains_key(&2));
+        let replayed =
+            Bank::replay_from_audit_trail(bank.audit_trail(), bank.config().clone()).unwrap();
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+    }
+
+    #[test]
+    fn list_all_kinds_seen() {
+        let mut bank = Bank::with_config(BankConfig {
+            track_kinds_seen: true,
+            ..BankConfig::default()
+        });
+        assert!(bank.list_all_kinds_seen().is_empty());
+
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 1.0.into()).unwrap();
+        assert_eq!(
+            bank.list_all_kinds_seen(),
+            HashSet::from([Kind::Deposit, Kind::Withdrawal])
+        );
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_resolve_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_chargeback_raw(1, 2).unwrap();
+        let _ = bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 3, None));
+        // Failed transactions are still seen.
+        let adjustment = Transaction::new(Kind::Adjustment, 1, 4, Some(1.0.into()));
+        bank.process_transaction(adjustment).unwrap_err();
+
+        assert_eq!(
+            bank.list_all_kinds_seen(),
+            HashSet::from([
+                Kind::Deposit,
+                Kind::Withdrawal,
+                Kind::Dispute,
+                Kind::Resolve,
+                Kind::Chargeback,
+                Kind::AdminReversal,
+                Kind::Adjustment,
+            ])
+        );
+
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        assert!(bank.list_all_kinds_seen().is_empty());
+    }
+
+    #[test]
+    fn audit_trail_disabled() {
+        let bank = disputed_bank();
+        assert!(bank.audit_trail().is_empty());
+    }
+
+    #[test]
+    fn simulate_transaction() {
+        let bank = disputed_bank();
+
+        let deposit = Transaction::new(Kind::Deposit, 1, 2, Some(5.0.into()));
+        assert_eq!(
+            bank.simulate_transaction(&deposit),
+            Ok(SimulationResult {
+                would_succeed: true,
+                new_available: 5.0.into(),
+                new_held: 10.0.into(),
+                new_total: 15.0.into(),
+            })
+        );
+        assert_eq!(bank.accounts[&1].available(), 0.into());
+        assert!(!bank.transactions.contains_key(&2));
+
+        let withdrawal = Transaction::new(Kind::Withdrawal, 1, 3, Some(5.0.into()));
+        let result = bank.simulate_transaction(&withdrawal).unwrap();
+        assert!(!result.would_succeed);
+        assert_eq!(result.new_available, 0.into());
+
+        let unknown = Transaction::new(Kind::Withdrawal, 2, 4, Some(5.0.into()));
+        assert_eq!(
+            bank.simulate_transaction(&unknown),
+            Err(BankError::AccountNotFound(2))
+        );
+    }
+
+    #[test]
+    fn bulk_credit() {
+        let mut bank = disputed_bank();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        bank.process_deposit_raw(2, TransactionID::MAX, 1.0.into())
+            .unwrap();
+
+        let credits = vec![(1, 5.0.into()), (2, 5.0.into()), (3, 2.0.into())];
+        let result = bank.apply_bulk_credit(credits.into_iter());
+
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(
+            result.failed,
+            vec![(1, BankError::Account(AccountError::AccountLocked))]
+        );
+        assert_eq!(bank.accounts[&1].available(), 0.into());
+        assert_eq!(bank.accounts[&2].available(), 6.0.into());
+        assert_eq!(bank.accounts[&3].available(), 2.0.into());
+        assert_eq!(result.total_credited, 7.0.into());
+
+        // Generated ids never collide with existing transactions.
+        assert_eq!(bank.transactions.len(), 4);
+        assert_eq!(
+            bank.transactions[&TransactionID::MAX].amount(),
+            Some(1.0.into())
+        );
+    }
+
+    #[test]
+    fn bulk_totals_saturate() {
+        let mut bank = Bank::new();
+        let large: Amount = 900_000_000_000_000.0.into();
+        let credits = vec![(1, large), (2, large)];
+        let result = bank.apply_bulk_credit(credits.clone().into_iter());
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(result.total_credited, Amount::MAX);
+
+        let result = bank.apply_bulk_debit(credits.into_iter());
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(result.total_debited, Amount::MAX);
+    }
+
+    #[test]
+    fn bulk_debit() {
+        let mut bank = disputed_bank();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 10.0.into()).unwrap();
+
+        let debits = vec![
+            (2, 20.0.into()),
+            (1, 1.0.into()),
+            (2, 4.0.into()),
+            (4, 1.0.into()),
+            (3, 2.5.into()),
+        ];
+        let result = bank.apply_bulk_debit(debits.into_iter());
+
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(
+            result.failed,
+            vec![
+                (2, BankError::Account(AccountError::InsufficientFunds)),
+                (1, BankError::Account(AccountError::AccountLocked)),
+                (4, BankError::AccountNotFound(4)),
+            ]
+        );
+        assert_eq!(result.total_debited, 6.5.into());
+        assert_eq!(bank.accounts[&2].available(), 6.0.into());
+        assert_eq!(bank.accounts[&3].available(), 7.5.into());
+    }
+
+    #[test]
+    fn batch_dispute() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 4, 5.0.into()).unwrap();
+
+        let result = bank.batch_dispute(&[2, 1, 4, 3, 5], 1);
+        assert_eq!(
+            result,
+            BatchDisputeResult {
+                succeeded: vec![2, 3],
+                failed: vec![
+                    (1, BankError::AlreadyDisputed(1)),
+                    (4, BankError::ClientMismatch(4)),
+                    (5, BankError::TransactionNotFound(5)),
+                ],
+            }
+        );
+        assert_eq!(bank.accounts[&1].held(), 20.0.into());
+        assert_eq!(bank.count_open_disputes_for_client(1), 3);
+    }
+
+    #[test]
+    fn batch_resolve() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 4, 5.0.into()).unwrap();
+        bank.batch_dispute(&[2], 1);
+        bank.batch_dispute(&[3, 4], 2);
+
+        let result = bank.batch_resolve(&[1, 3, 5, 1]);
+        assert_eq!(
+            result,
+            BatchResolveResult {
+                succeeded: vec![1, 3],
+                failed: vec![
+                    (5, BankError::DisputeNotFound(5)),
+                    (1, BankError::DisputeNotFound(1)),
+                ],
+            }
+        );
+        assert_eq!(bank.accounts[&1].held(), 5.0.into());
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+        assert_eq!(bank.accounts[&2].held(), 5.0.into());
+
+        let result = bank.batch_chargeback(&[2, 4, 3]);
+        assert_eq!(
+            result,
+            BatchChargebackResult {
+                succeeded: vec![2, 4],
+                failed: vec![(3, BankError::DisputeNotFound(3))],
+            }
+        );
+        for client in [1, 2] {
+            let account = &bank.accounts[&client];
+            assert!(account.is_locked());
+            assert_eq!(account.held(), 0.into());
+        }
+        assert!(bank.disputes.is_empty());
+        assert_eq!(bank.reconcile(), Ok(()));
+    }
+
+    #[test]
+    fn accounts_with_negative_available() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.set_account_limit(1, 5.0.into()).unwrap();
+        bank.set_account_limit(2, 5.0.into()).unwrap();
+        assert_eq!(bank.accounts_with_negative_available().count(), 0);
+
+        bank.process_withdrawal_raw(1, 3, 12.5.into()).unwrap();
+        bank.process_withdrawal_raw(2, 4, 10.0.into()).unwrap();
+        assert_eq!(
+            bank.accounts_with_negative_available().collect::<Vec<_>>(),
+            vec![(1, (-2.5).into())]
+        );
+    }
+
+    #[test]
+    fn account_limit_locked_account() {
+        let mut bank = disputed_bank();
+        bank.set_account_limit(1, 5.0.into()).unwrap();
+        bank.process_chargeback_raw(1, 1).unwrap();
+
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 2, 20.0.into()),
+            Err(BankError::Account(AccountError::AccountLocked))
+        );
+    }
+
+    #[test]
+    fn account_limit() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.set_account_limit(1, 5.0.into()).unwrap();
+
+        bank.process_withdrawal_raw(1, 2, 12.0.into()).unwrap();
+        assert_eq!(bank.accounts[&1].available(), (-2.0).into());
+
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 3, 4.0.into()),
+            Err(BankError::ExceedsAccountLimit)
+        );
+        assert_eq!(bank.accounts[&1].available(), (-2.0).into());
+
+        assert_eq!(
+            bank.set_account_limit(2, 5.0.into()),
+            Err(BankError::AccountNotFound(2))
+        );
+    }
+
+    #[test]
+    fn percentage_fee() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 200.0.into()).unwrap();
+
+        assert_eq!(bank.apply_percentage_fee(1, 1.5), Ok(3.0.into()));
+        assert_eq!(bank.accounts[&1].available(), 197.0.into());
+        let entry = bank.account_statement(1).last().unwrap();
+        assert_eq!(entry.kind, Kind::Withdrawal);
+        assert_eq!(entry.amount, Some(3.0.into()));
+        assert_eq!(entry.memo.as_deref(), Some("fee"));
+
+        assert_eq!(bank.apply_percentage_fee(1, 10.0), Ok(19.7.into()));
+        assert_eq!(bank.accounts[&1].available(), 177.3.into());
+        assert_eq!(bank.accounts[&1].total_fees_paid(), 22.7.into());
+
+        assert_eq!(
+            bank.apply_percentage_fee(2, 1.5),
+            Err(BankError::AccountNotFound(2))
+        );
+
+        bank.process_deposit_raw(1, 2, 3.0.into()).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_chargeback_raw(1, 2).unwrap();
+        assert_eq!(
+            bank.apply_percentage_fee(1, 1.5),
+            Err(AccountError::AccountLocked.into())
+        );
+        assert_eq!(bank.accounts[&1].available(), 177.3.into());
+        assert_eq!(bank.accounts[&1].total_fees_paid(), 22.7.into());
+    }
+
+    #[test]
+    fn fees_are_bank_initiated() {
+        let mut bank = Bank::with_config(BankConfig {
+            max_velocity: Some((1, 3600)),
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 200.0.into()).unwrap();
+
+        assert_eq!(bank.apply_percentage_fee(1, 0.0), Ok(Amount::new()));
+        assert_eq!(bank.transactions.len(), 1);
+        assert_eq!(bank.audit_trail().len(), 1);
+
+        assert_eq!(bank.apply_percentage_fee(1, 1.5), Ok(3.0.into()));
+        let report = bank.process_end_of_period(PeriodConfig {
+            maintenance_fee: Some(1.0.into()),
+            interest_rate: Some(10.0),
+            ..PeriodConfig::default()
+        });
+        assert_eq!(report.fees_collected, 1.0.into());
+        assert_eq!(report.interest_paid, 19.6.into());
+        assert_eq!(report.failed, vec![]);
+
+        let account = &bank.accounts[&1];
+        assert_eq!(account.available(), 215.6.into());
+        assert_eq!(account.total_fees_paid(), 4.0.into());
+        assert!(account.is_new());
+        assert_eq!(bank.transaction_velocity(1, 3600), 1);
+
+        let replayed =
+            Bank::replay_from_audit_trail(bank.audit_trail(), bank.config().clone()).unwrap();
+        assert_eq!(replayed.accounts[&1].total_fees_paid(), 4.0.into());
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+    }
+
+    #[test]
+    fn total_fees_collected() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.total_fees_collected(), Amount::new());
+
+        for client in 1..=3 {
+            bank.process_deposit_raw(client, client.into(), 100.0.into())
+                .unwrap();
+        }
+        bank.apply_percentage_fee(1, 1.0).unwrap();
+        bank.apply_percentage_fee(1, 1.0).unwrap();
+        bank.apply_percentage_fee(2, 2.5).unwrap();
+
+        let sum: Amount = bank.accounts.values().map(Account::total_fees_paid).sum();
+        assert_eq!(bank.total_fees_collected(), sum);
+        assert_eq!(sum, 4.49.into());
+    }
+
+    #[test]
+    fn initial_deposit_bonus() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            initial_deposit_bonus: Some(5.0.into()),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 1.0.into()).unwrap();
+
+        assert_eq!(bank.accounts[&1].available(), 25.0.into());
+        assert_eq!(bank.accounts[&2].available(), 6.0.into());
+
+        let statement: Vec<_> = bank
+            .account_statement(1)
+            .iter()
+            .map(|e| (e.amount.unwrap(), e.memo.as_deref()))
+            .collect();
+        assert_eq!(
+            statement,
+            vec![
+                (10.0.into(), None),
+                (5.0.into(), Some("initial deposit bonus")),
+                (10.0.into(), None),
+            ]
+        );
+        let bonus_id = bank.account_statement(1)[1].tx_id;
+        assert_eq!(bank.transactions[&bonus_id].client(), 1);
+        assert_eq!(bank.reconcile(), Ok(()));
+
+        // The bonus is not counted as activity of the client.
+        assert_eq!(bank.accounts[&2].transaction_count(), 1);
+        assert!(bank.accounts[&2].is_new());
+
+        // Failed deposits don't create the account, so don't earn the bonus either.
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: Some(5.0.into()),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, (-1.0).into()).unwrap_err();
+        assert!(bank.accounts.is_empty());
+        assert!(bank.transactions.is_empty());
+    }
+
+    #[test]
+    fn initial_deposit_bonus_audit_and_velocity() {
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: Some(5.0.into()),
+            max_velocity: Some((2, 10)),
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        assert_eq!(bank.transaction_velocity(1, 10), 1);
+        bank.process_deposit_raw(1, 2, 1.0.into()).unwrap();
+        assert_eq!(
+            bank.process_deposit_raw(1, 3, 1.0.into()),
+            Err(BankError::VelocityLimitExceeded(1))
+        );
+        assert_eq!(bank.accounts[&1].available(), 16.0.into());
+        assert_eq!(bank.accounts[&1].transaction_count(), 2);
+
+        let trail: Vec<_> = bank
+            .audit_trail()
+            .iter()
+            .map(|e| {
+                let origin = e.metadata().get(ORIGIN_METADATA_KEY).map(String::as_str);
+                (e.transaction().amount(), e.outcome().is_ok(), origin)
+            })
+            .collect();
+        assert_eq!(
+            trail,
+            vec![
+                (Some(10.0.into()), true, None),
+                (Some(5.0.into()), true, Some("bank")),
+                (Some(1.0.into()), true, None),
+                (Some(1.0.into()), false, None),
+            ]
+        );
+
+        let replayed =
+            Bank::replay_from_audit_trail(bank.audit_trail(), bank.config().clone()).unwrap();
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+    }
+
+    #[test]
+    fn account_is_new() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        assert!(bank.accounts[&1].is_new());
+
+        bank.process_withdrawal_raw(1, 2, 20.0.into()).unwrap_err();
+        assert!(bank.accounts[&1].is_new());
+
+        bank.process_deposit_raw(1, 3, 10.0.into()).unwrap();
+        assert!(!bank.accounts[&1].is_new());
+        assert_eq!(bank.accounts[&1].transaction_count(), 2);
+    }
+
+    #[test]
+    fn account_age_in_transactions() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.account_age_in_transactions(1), None);
+
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        assert_eq!(bank.account_age_in_transactions(1), Some(1));
+        bank.process_dispute_raw(1, 1).unwrap();
+        assert_eq!(bank.account_age_in_transactions(1), Some(2));
+        bank.process_withdrawal_raw(1, 2, 1.0.into()).unwrap_err();
+        bank.process_resolve_raw(1, 1).unwrap();
+        assert_eq!(bank.account_age_in_transactions(1), Some(3));
+    }
+
+    #[test]
+    fn process_end_of_period() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 100.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 50.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 10.0.into()).unwrap();
+        bank.process_deposit_raw(3, 4, 0.5.into()).unwrap();
+        bank.process_deposit_raw(4, 5, 20.0.into()).unwrap();
+        bank.process_dispute_raw(4, 5).unwrap();
+        bank.process_chargeback_raw(4, 5).unwrap();
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        clock.advance(Duration::from_secs(60));
+        bank.process_dispute_raw(2, 3).unwrap();
+        clock.advance(Duration::from_secs(30));
+
+        let report = bank.process_end_of_period(PeriodConfig {
+            maintenance_fee: Some(1.0.into()),
+            interest_rate: Some(10.0),
+            expire_old_disputes: Some(Duration::from_secs(60)),
+            flag_suspicious_accounts: true,
+        });
+
+        assert_eq!(
+            report,
+            PeriodReport {
+                fees_collected: 1.0.into(),
+                // Disputed funds earn no interest, so account 1 earns nothing.
+                interest_paid: 4.95.into(),
+                expired_disputes: vec![1],
+                flagged_accounts: vec![4],
+                failed: vec![
+                    (1, AccountError::InsufficientFunds.into()),
+                    (3, AccountError::InsufficientFunds.into()),
+                    (4, AccountError::AccountLocked.into()),
+                ],
+            }
+        );
+
+        // The expired dispute released the funds of account 1.
+        assert_eq!(bank.accounts[&1].available(), 100.0.into());
+        assert_eq!(bank.accounts[&2].available(), 53.9.into());
+        assert_eq!(bank.accounts[&3].available(), 0.55.into());
+        assert!(bank.accounts[&4].is_flagged_for_review());
+        assert!(!bank.accounts[&1].is_flagged_for_review());
+        assert_eq!(bank.total_fees_collected(), 1.0.into());
+        assert_eq!(bank.reconcile(), Ok(()));
+    }
+
+    #[test]
+    fn process_end_of_period_expires_frozen_disputes() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            clock: clock.clone(),
+            max_velocity: Some((2, 3600)),
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.freeze_account(1).unwrap();
+        clock.advance(Duration::from_secs(60));
+
+        let report = bank.process_end_of_period(PeriodConfig {
+            expire_old_disputes: Some(Duration::from_secs(60)),
+            ..PeriodConfig::default()
+        });
+        assert_eq!(report.expired_disputes, vec![1]);
+        assert_eq!(report.failed, vec![]);
+
+        let account = &bank.accounts[&1];
+        assert_eq!(account.available(), 10.0.into());
+        assert_eq!(account.transaction_count(), 2);
+        assert_eq!(
+            account.dispute_history(),
+            &[(1, DisputeResolution::AutoExpired)]
+        );
+        assert_eq!(bank.transaction_velocity(1, 3600), 2);
+
+        let entry = bank.audit_trail().last().unwrap();
+        assert_eq!(entry.transaction().kind(), Kind::Resolve);
+        assert_eq!(entry.resolution(), Some(DisputeResolution::AutoExpired));
+        assert!(entry.outcome().is_ok());
+    }
+
+    #[test]
+    fn minimum_balance() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 50.0.into()).unwrap();
+        bank.set_minimum_balance(1, 100.0.into()).unwrap();
+        assert_eq!(bank.accounts[&1].minimum_balance(), Some(100.0.into()));
+
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 2, 10.0.into()),
+            Err(BankError::Account(AccountError::MinimumBalanceViolation {
+                minimum: 100.0.into(),
+                would_result_in: 40.0.into(),
+            }))
+        );
+        assert_eq!(bank.accounts[&1].available(), 50.0.into());
+
+        bank.process_deposit_raw(1, 3, 60.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 10.0.into()).unwrap();
+        assert!(bank.process_withdrawal_raw(1, 5, 0.5.into()).is_err());
+
+        bank.set_minimum_balance(1, Amount::new()).unwrap();
+        assert_eq!(bank.accounts[&1].minimum_balance(), None);
+        bank.process_withdrawal_raw(1, 6, 100.0.into()).unwrap();
+
+        assert_eq!(
+            bank.set_minimum_balance(2, 5.0.into()),
+            Err(BankError::AccountNotFound(2))
+        );
+        assert_eq!(
+            bank.set_minimum_balance(1, (-5.0).into()),
+            Err(BankError::NegativeAmount)
+        );
+    }
+
+    #[test]
+    fn transactions_for_client() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 1.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+
+        let mut ids: Vec<TransactionID> = bank.transaction_ids_for_client(1).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 3, 4]);
+
+        assert!(bank
+            .find_transactions_for_client(2)
+            .all(|t| t.client() == 2 && t.id() == 2));
+        assert_eq!(bank.transaction_ids_for_client(3).count(), 0);
+    }
+
+    #[test]
+    fn all_transaction_ids() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 3.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 3, 1.0.into()).unwrap();
+        bank.process_deposit_raw(2, 4, 1.0.into()).unwrap();
+        bank.process_dispute_raw(2, 4).unwrap();
+        bank.process_chargeback_raw(2, 4).unwrap();
+
+        let ids: Vec<TransactionID> = bank.all_transaction_ids().collect();
+        assert_eq!(ids.len(), bank.transactions.len() + bank.disputes.len());
+
+        let expected: HashSet<TransactionID> = bank
+            .transactions
+            .keys()
+            .chain(bank.disputes.keys())
+            .copied()
+            .collect();
+        assert_eq!(ids.into_iter().collect::<HashSet<_>>(), expected);
+        assert_eq!(expected, HashSet::from([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn highest_transaction_id_seen() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.highest_transaction_id_seen(), None);
+
+        bank.process_deposit_raw(1, 7, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 12, 1.0.into()).unwrap_err();
+        assert_eq!(bank.highest_transaction_id_seen(), Some(7));
+
+        bank.process_withdrawal_raw(1, 9, 1.0.into()).unwrap();
+        assert_eq!(bank.highest_transaction_id_seen(), Some(9));
+    }
+
+    #[test]
+    fn transaction_id_gaps() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.transaction_id_gaps(), vec![]);
+
+        for tx in [1, 2, 5, 6] {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        assert_eq!(bank.transaction_id_gaps(), vec![(3, 4)]);
+
+        bank.process_deposit_raw(1, 8, 1.0.into()).unwrap();
+        bank.transactions.remove(&1);
+        assert_eq!(bank.transaction_id_gaps(), vec![(1, 1), (3, 4), (7, 7)]);
+
+        // Ids only known through an open dispute are missing too.
+        bank.process_deposit_raw(1, 10, 1.0.into()).unwrap();
+        bank.process_dispute_raw(1, 10).unwrap();
+        bank.transactions.remove(&10);
+        assert_eq!(
+            bank.transaction_id_gaps(),
+            vec![(1, 1), (3, 4), (7, 7), (9, 10)]
+        );
+    }
+
+    #[test]
+    fn transaction_id_gaps_with_generated_ids() {
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: Some(5.0.into()),
+            ..BankConfig::default()
+        });
+        for tx in [1, 2, 5] {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        bank.charge_fee(1, 1.0.into(), "monthly fee").unwrap();
+        assert_eq!(bank.transactions.len(), 5);
+
+        assert_eq!(bank.highest_transaction_id_seen(), Some(5));
+        assert_eq!(bank.transaction_id_gaps(), vec![(3, 4)]);
+
+        // Upstream ids at the top of the id space are not mistaken for generated ones.
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: Some(5.0.into()),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, TransactionID::MAX, 1.0.into())
+            .unwrap();
+        assert_eq!(bank.transactions.len(), 2);
+        assert_eq!(bank.highest_transaction_id_seen(), Some(TransactionID::MAX));
+    }
+
+    #[test]
+    fn replayed_generated_ids() {
+        let mut bank = Bank::with_config(BankConfig {
+            initial_deposit_bonus: Some(5.0.into()),
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.apply_percentage_fee(1, 10.0).unwrap();
+        assert_eq!(bank.highest_transaction_id_seen(), Some(1));
+        assert_eq!(bank.transaction_id_gaps(), vec![]);
+
+        let mut replayed =
+            Bank::replay_from_audit_trail(bank.audit_trail(), bank.config().clone()).unwrap();
+        assert_eq!(replayed.highest_transaction_id_seen(), Some(1));
+        assert_eq!(replayed.transaction_id_gaps(), vec![]);
+        assert_eq!(replayed.accounts[&1].available(), 13.5.into());
+
+        // Ids generated after the replay do not collide with the replayed ones.
+        replayed.apply_percentage_fee(1, 10.0).unwrap();
+        bank.apply_percentage_fee(1, 10.0).unwrap();
+        assert_eq!(bank.diff(&replayed), BankDiff::default());
+    }
+
+    #[test]
+    fn contains_transaction() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 3.0.into()).unwrap();
+        // Only known through the open dispute.
+        bank.transactions.remove(&1);
+
+        assert!(bank.contains_transaction(1));
+        assert!(bank.contains_transaction(2));
+        assert!(!bank.contains_transaction(3));
+    }
+
+    #[test]
+    fn contains_account() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 1.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 1.0.into()).unwrap();
+        bank.retain_accounts(|client, _| client != 2);
+
+        assert!(bank.contains_account(1));
+        assert!(!bank.contains_account(2));
+        assert!(!bank.contains_account(3));
+    }
+
+    #[test]
+    fn compliance_report() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10_000.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 9_999.999_9.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 25_000.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 4, 12_000.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 5, 10.0.into()).unwrap();
+
+        assert_eq!(
+            bank.compliance_report(10_000.0.into()),
+            ComplianceReport {
+                threshold: 10_000.0.into(),
+                transactions: vec![
+                    (1, 1, 10_000.0.into()),
+                    (3, 2, 25_000.0.into()),
+                    (4, 2, 12_000.0.into()),
+                ],
+            }
+        );
+        assert!(bank
+            .compliance_report(50_000.0.into())
+            .transactions
+            .is_empty());
+    }
+
+    #[test]
+    fn flagged_transactions() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 500.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 12.34.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 1000.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 4, 999.99.into()).unwrap();
+        bank.process_withdrawal_raw(1, 5, 20.0.into()).unwrap();
+
+        let round = bank.flagged_transactions(|t| t.amount_or_zero().to_cents() % 100 == 0);
+        assert_eq!(round, vec![1, 3, 5]);
+
+        let threshold: Amount = 999.0.into();
+        let large = bank.flagged_transactions(|t| t.amount_or_zero() > threshold);
+        assert_eq!(large, vec![3, 4]);
+
+        assert!(bank
+            .flagged_transactions(|t| t.kind() == Kind::Dispute)
+            .is_empty());
+    }
+
+    #[test]
+    fn has_open_disputes_for_client() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        assert!(!bank.has_open_disputes_for_client(1));
+
+        bank.process_dispute_raw(2, 2).unwrap();
+        assert!(!bank.has_open_disputes_for_client(1));
+        assert!(bank.has_open_disputes_for_client(2));
+
+        bank.process_dispute_raw(1, 1).unwrap();
+        assert!(bank.has_open_disputes_for_client(1));
+        bank.process_resolve_raw(1, 1).unwrap();
+        assert!(!bank.has_open_disputes_for_client(1));
+    }
+
+    #[test]
+    fn count_open_disputes_for_client() {
+        let mut bank = Bank::new();
+        for tx in 1..=3 {
+            bank.process_deposit_raw(1, tx, 10.0.into()).unwrap();
+        }
+        bank.process_deposit_raw(2, 4, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 3).unwrap();
+        bank.process_dispute_raw(2, 4).unwrap();
+
+        assert_eq!(bank.count_open_disputes_for_client(1), 2);
+        assert_eq!(bank.count_open_disputes_for_client(2), 1);
+        assert_eq!(bank.count_open_disputes_for_client(3), 0);
+    }
+
+    #[test]
+    fn account_summary() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 2.0.into()).unwrap();
+        bank.apply_percentage_fee(1, 10.0).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_chargeback_raw(1, 1).unwrap();
+
+        assert_eq!(
+            bank.account_summary(1),
+            Some(AccountSummary {
+                client: 1,
+                available: 0.3.into(),
+                held: 5.0.into(),
+                total: 5.3.into(),
+                is_locked: true,
+                is_frozen: false,
+                open_disputes: 1,
+                // The fee is charged by the bank, so it is not counted.
+                total_transactions: 6,
+                chargebacks: 1,
+                fees_paid: 1.7.into(),
+            })
+        );
+        assert_eq!(bank.account_summary(2), None);
+    }
+
+    #[test]
+    fn bulk_account_summaries() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 1.0.into()).unwrap();
+
+        let summaries = bank.bulk_account_summaries(&[1, 3, 9, 1]);
+        let mut ids: Vec<_> = summaries.keys().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![1, 3]);
+        for (id, summary) in &summaries {
+            assert_eq!(Some(summary), bank.account_summary(*id).as_ref());
+        }
+        assert_eq!(summaries[&1].open_disputes, 1);
+        assert_eq!(summaries[&3].open_disputes, 0);
+        assert!(bank.bulk_account_summaries(&[]).is_empty());
+    }
+
+    #[test]
+    fn force_chargeback_all_disputes_for_client() {
+        let mut bank = Bank::new();
+        for tx in 1..=3 {
+            bank.process_deposit_raw(1, tx, 10.0.into()).unwrap();
+        }
+        bank.process_deposit_raw(2, 4, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_dispute_raw(2, 4).unwrap();
+
+        assert_eq!(bank.force_chargeback_all_disputes_for_client(1), 2);
+        let account = &bank.accounts[&1];
+        assert!(account.is_locked());
+        assert_eq!(account.held(), 0.into());
+        assert_eq!(account.available(), 10.0.into());
+        assert_eq!(account.chargeback_count(), 2);
+        assert_eq!(bank.count_disputes_per_account(), HashMap::from([(2, 1)]));
+        assert_eq!(bank.reconcile(), Ok(()));
+
+        bank.process_deposit_raw(3, 5, 1.0.into()).unwrap();
+        assert_eq!(bank.force_chargeback_all_disputes_for_client(3), 0);
+        assert!(bank.accounts[&3].is_locked());
+        assert_eq!(bank.force_chargeback_all_disputes_for_client(4), 0);
+    }
+
+    #[test]
+    fn force_chargeback_all_disputes_for_frozen_client() {
+        let mut bank = Bank::with_config(BankConfig {
+            max_velocity: Some((4, 60)),
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.freeze_account(1).unwrap();
+        bank.process_withdrawal_raw(1, 3, 1.0.into()).unwrap_err();
+
+        assert_eq!(bank.force_chargeback_all_disputes_for_client(1), 2);
+        let account = &bank.accounts[&1];
+        assert!(account.is_locked());
+        assert_eq!(account.total(), 0.into());
+        assert_eq!(account.chargeback_count(), 2);
+        assert_eq!(bank.transaction_velocity(1, 60), 4);
+        assert_eq!(bank.transaction_error_rate(2), 0.0);
+
+        let chargebacks: Vec<_> = bank
+            .audit_trail()
+            .iter()
+            .filter(|e| e.transaction().kind() == Kind::Chargeback)
+            .map(|e| (e.transaction().id(), e.outcome().clone()))
+            .collect();
+        assert_eq!(
+            chargebacks,
+            vec![(1, AuditOutcome::Ok), (2, AuditOutcome::Ok)]
+        );
+    }
+
+    #[test]
+    fn pending_dispute_amounts_per_account() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 3.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 4.0.into()).unwrap();
+        bank.process_dispute_raw(2, 3).unwrap();
+        bank.process_deposit_raw(3, 4, 1.0.into()).unwrap();
+
+        let amounts = bank.pending_dispute_amounts_per_account();
+        assert_eq!(amounts.len(), 3);
+        for (id, amount) in &amounts {
+            assert_eq!(*amount, bank.get_account(*id).unwrap().held());
+        }
+        assert_eq!(amounts[&1], 10.0.into());
+        assert_eq!(amounts[&2], 4.0.into());
+        assert_eq!(amounts[&3], 0.into());
+        assert!(bank.get_account(4).is_none());
+    }
+
+    #[test]
+    fn oldest_dispute() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        assert_eq!(bank.oldest_dispute(), None);
+
+        for tx in 1..=4 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        // Disputes 2 and 3 are opened at the same time.
+        for (tx, secs) in [(2, 0), (3, 0), (4, 10), (1, 10)] {
+            clock.advance(Duration::from_secs(secs));
+            bank.process_dispute_raw(1, tx).unwrap();
+        }
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(bank.oldest_dispute(), Some((2, Duration::from_secs(25))));
+
+        bank.process_resolve_raw(1, 2).unwrap();
+        assert_eq!(bank.oldest_dispute().unwrap().0, 3);
+        bank.process_chargeback_raw(1, 3).unwrap();
+        assert_eq!(bank.oldest_dispute().unwrap().0, 4);
+    }
+
+    #[test]
+    fn close_dispute_with_reason() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+
+        let reasons = [
+            DisputeResolution::MerchantAccepted,
+            DisputeResolution::BankDecision,
+            DisputeResolution::AutoExpired,
+            DisputeResolution::CustomerWithdrawn,
+        ];
+        for (tx, reason) in (1..).zip(reasons) {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+            bank.process_dispute_raw(1, tx).unwrap();
+            bank.close_dispute_with_reason(tx, reason, false).unwrap();
+        }
+
+        bank.process_deposit_raw(2, 5, 1.0.into()).unwrap();
+        bank.process_dispute_raw(2, 5).unwrap();
+        bank.close_dispute_with_reason(5, DisputeResolution::BankDecision, true)
+            .unwrap();
+        assert!(bank.accounts[&2].is_locked());
+
+        assert_eq!(
+            bank.close_dispute_with_reason(5, DisputeResolution::BankDecision, true),
+            Err(BankError::DisputeNotFound(5))
+        );
+
+        let history: Vec<DisputeResolution> = bank.accounts[&1]
+            .dispute_history()
+            .iter()
+            .map(|(_, reason)| *reason)
+            .collect();
+        assert_eq!(history, reasons);
+        assert_eq!(
+            bank.accounts[&2].dispute_history(),
+            &[(5, DisputeResolution::BankDecision)]
+        );
+
+        let audited: Vec<(Kind, DisputeResolution)> = bank
+            .audit_trail()
+            .iter()
+            .filter_map(|e| e.resolution().map(|r| (e.transaction().kind(), r)))
+            .collect();
+        assert_eq!(audited.len(), 5);
+        assert_eq!(audited[0], (Kind::Resolve, reasons[0]));
+        assert_eq!(
+            audited[4],
+            (Kind::Chargeback, DisputeResolution::BankDecision)
+        );
+    }
+
+    #[test]
+    fn process_from_iterator() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,x,5.0
+withdrawal,1,3,20.0
+transfer,1,4,1.0
+withdrawal,1,5,2.5
+";
+        let mut bank = Bank::new();
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let result = bank.process_from_iterator(reader.deserialize());
+
+        assert_eq!(
+            result,
+            ProcessResult {
+                succeeded: 2,
+                failed: vec![(3, BankError::Account(AccountError::InsufficientFunds))],
+                parse_errors: 2,
+            }
+        );
+        assert_eq!(bank.accounts[&1].available(), 7.5.into());
+    }
+
+    #[test]
+    fn process_all_consistency() {
+        let mut bank = Bank::new();
+        let result = bank.process_all(vec![
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())),
+            Transaction::new(Kind::Deposit, 1, 2, Some(5.0.into())),
+            Transaction::new(Kind::Withdrawal, 1, 3, Some(3.0.into())),
+            Transaction::new(Kind::Dispute, 1, 1, None),
+            Transaction::new(Kind::Chargeback, 1, 1, None),
+            Transaction::new(Kind::Deposit, 2, 4, Some(7.0.into())),
+            Transaction::new(Kind::Dispute, 2, 4, None),
+            Transaction::new(Kind::AdminReversal, 1, 2, None),
+            Transaction::new(Kind::Resolve, 2, 9, None),
+        ]);
+
+        assert_eq!(result.succeeded, 7);
+        assert_eq!(
+            result.failed,
+            vec![
+                (2, BankError::Account(AccountError::AccountLocked)),
+                (9, BankError::DisputeNotFound(9)),
+            ]
+        );
+        assert_eq!(bank.validate_consistency(), Ok(()));
+        assert_eq!(bank.reconcile(), Ok(()));
+    }
+
+    #[test]
+    fn validate_consistency_violations() {
+        let mut account = Account::new();
+        account.try_credit(5.0.into()).unwrap();
+        account.try_dispute(5.0.into()).unwrap();
+        let bank = Bank::with_accounts([(1, account)]);
+
+        assert_eq!(
+            bank.validate_consistency(),
+            Err(ConsistencyError::HeldMismatch(1))
+        );
+        assert_eq!(
+            bank.reconcile(),
+            Err(ConsistencyError::LedgerMismatch {
+                expected: Amount::new(),
+                actual: 5.0.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn verify_transaction_integrity() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+        assert_eq!(bank.verify_transaction_integrity(1), Ok(()));
+        assert_eq!(bank.verify_transaction_integrity(2), Ok(()));
+        assert_eq!(
+            bank.verify_transaction_integrity(3),
+            Err(IntegrityError::TransactionNotFound(3))
+        );
+
+        let mut broken = bank.clone();
+        broken.transactions.remove(&1);
+        assert_eq!(
+            broken.verify_transaction_integrity(1),
+            Err(IntegrityError::DisputeWithoutTransaction(1))
+        );
+
+        let mut broken = bank.clone();
+        broken
+            .transactions
+            .get_mut(&2)
+            .unwrap()
+            .set_amount(Some(Amount::new()));
+        assert_eq!(
+            broken.verify_transaction_integrity(2),
+            Err(IntegrityError::InvalidAmount(2))
+        );
+        broken.transactions.get_mut(&2).unwrap().set_amount(None);
+        assert_eq!(
+            broken.verify_transaction_integrity(2),
+            Err(IntegrityError::InvalidAmount(2))
+        );
+
+        let mut broken = bank.clone();
+        broken.accounts.remove(&2);
+        assert_eq!(
+            broken.verify_transaction_integrity(2),
+            Err(IntegrityError::AccountNotFound(2))
+        );
+        assert_eq!(broken.verify_transaction_integrity(1), Ok(()));
+    }
+
+    #[test]
+    fn enforce_non_negative_invariant() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.set_account_limit(2, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 3, 12.0.into()).unwrap();
+        assert_eq!(
+            bank.process_withdrawal_raw(1, 4, 12.0.into()),
+            Err(BankError::Account(AccountError::InsufficientFunds))
+        );
+        assert!(bank.enforce_non_negative_invariant().is_empty());
+
+        let mut limited = Account::with_balances((-1.0).into(), Amount::new(), false);
+        limited.set_withdrawal_limit(Some(5.0.into()));
+        let bank = Bank::with_accounts([
+            (3, Account::with_balances((-1.0).into(), 2.0.into(), false)),
+            (
+                1,
+                Account::with_balances((-0.0001).into(), Amount::new(), true),
+            ),
+            (2, limited),
+            (4, Account::with_balances(Amount::new(), 1.0.into(), false)),
+        ]);
+        assert_eq!(bank.enforce_non_negative_invariant(), vec![1, 3]);
+    }
+
+    #[test]
+    fn dispute_after_withdrawal() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 100.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 60.0.into()).unwrap();
+
+        assert_eq!(bank.accounts[&1].available_for_dispute(), 40.0.into());
+        assert_eq!(
+            bank.process_dispute_raw(1, 1),
+            Err(BankError::Account(AccountError::InsufficientFunds))
+        );
+        assert_eq!(bank.accounts[&1].held(), Amount::new());
+    }
+
+    #[test]
+    fn top_n_accounts_by_total() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 20.0.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 10.0.into()).unwrap();
+        bank.process_deposit_raw(4, 4, 10.0.into()).unwrap();
+
+        assert_eq!(bank.top_n_accounts_by_total(0), vec![]);
+        assert_eq!(
+            bank.top_n_accounts_by_total(2),
+            vec![(2, 20.0.into()), (3, 10.0.into())]
+        );
+        assert_eq!(
+            bank.top_n_accounts_by_total(10),
+            vec![
+                (2, 20.0.into()),
+                (3, 10.0.into()),
+                (4, 10.0.into()),
+                (1, 5.0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bottom_n_accounts_by_available() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 20.0.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 10.0.into()).unwrap();
+        bank.process_deposit_raw(4, 4, 10.0.into()).unwrap();
+        bank.set_account_limit(4, 50.0.into()).unwrap();
+        bank.process_withdrawal_raw(4, 5, 30.0.into()).unwrap();
+        bank.set_account_limit(2, 50.0.into()).unwrap();
+        bank.process_withdrawal_raw(2, 6, 21.0.into()).unwrap();
+
+        assert_eq!(bank.bottom_n_accounts_by_available(0), vec![]);
+        assert_eq!(
+            bank.bottom_n_accounts_by_available(3),
+            vec![(4, (-20.0).into()), (2, (-1.0).into()), (1, 5.0.into())]
+        );
+        assert_eq!(bank.bottom_n_accounts_by_available(10).len(), 4);
+    }
+
+    #[test]
+    fn summarize_by_client_range() {
+        let mut bank = Bank::new();
+        for client in 1..=6 {
+            bank.process_deposit_raw(client, client.into(), 10.0.into())
+                .unwrap();
+        }
+        bank.process_dispute_raw(2, 2).unwrap();
+        bank.process_dispute_raw(5, 5).unwrap();
+        bank.process_chargeback_raw(5, 5).unwrap();
+
+        let low = bank.summarize_by_client_range(0, 3);
+        let high = bank.summarize_by_client_range(4, AccountID::MAX);
+        assert_eq!(
+            low,
+            RangeSummary {
+                account_count: 3,
+                total_assets: 30.0.into(),
+                total_held: 10.0.into(),
+                locked_count: 0,
+            }
+        );
+        assert_eq!(high.locked_count, 1);
+        assert_eq!(low.account_count + high.account_count, bank.accounts.len());
+        assert_eq!(low.total_assets + high.total_assets, bank.total_assets());
+        assert_eq!(low.total_held + high.total_held, bank.total_held());
+
+        bank.process_deposit_raw(1, 7, 900_000_000_000_000.0.into())
+            .unwrap();
+        bank.process_deposit_raw(3, 8, 900_000_000_000_000.0.into())
+            .unwrap();
+        assert_eq!(
+            bank.summarize_by_client_range(0, 3).total_assets,
+            Amount::MAX
+        );
+    }
+
+    #[test]
+    fn accounts_with_zero_balance() {
+        let mut bank = Bank::new();
+        assert_eq!(bank.accounts_with_zero_balance().count(), 0);
+
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 0.0001.into()).unwrap();
+        bank.process_deposit_raw(3, 4, 5.0.into()).unwrap();
+        bank.process_dispute_raw(3, 4).unwrap();
+
+        assert_eq!(
+            bank.accounts_with_zero_balance().collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn frozen_account() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 10.0.into()).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.freeze_account(1).unwrap();
+
+        let frozen = Err(BankError::Account(AccountError::AccountFrozen));
+        assert_eq!(bank.process_deposit_raw(1, 3, 1.0.into()), frozen);
+        assert_eq!(bank.process_withdrawal_raw(1, 4, 1.0.into()), frozen);
+        assert_eq!(bank.process_dispute_raw(1, 1), frozen);
+        assert_eq!(bank.process_resolve_raw(1, 2), frozen);
+        assert_eq!(bank.process_chargeback_raw(1, 2), frozen);
+        assert_eq!(bank.process_resolve_partial(2, 1.0.into()), frozen);
+        assert_eq!(
+            bank.process_transaction(Transaction::new(Kind::AdminReversal, 1, 1, None)),
+            frozen
+        );
+        assert!(bank.accounts[&1].is_frozen());
+        assert!(!bank.accounts[&1].is_locked());
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+
+        bank.unfreeze_account(1).unwrap();
+        bank.process_deposit_raw(1, 3, 1.0.into()).unwrap();
+        bank.process_chargeback_raw(1, 2).unwrap();
+        assert!(bank.accounts[&1].is_locked());
+        assert!(!bank.accounts[&1].is_frozen());
+
+        assert_eq!(bank.freeze_account(9), Err(BankError::AccountNotFound(9)));
+    }
+
+    #[test]
+    fn locked_and_frozen_accounts() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(3, 3, 10.0.into()).unwrap();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        bank.freeze_account(2).unwrap();
+
+        assert_eq!(bank.locked_accounts().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(bank.frozen_accounts().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn idempotency() {
+        let mut bank = Bank::new();
+        let deposit = Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into()));
+        let withdrawal = Transaction::new(Kind::Withdrawal, 1, 2, Some(20.0.into()));
+
+        assert_eq!(
+            bank.process_transaction_with_idempotency(deposit.clone(), "a"),
+            Ok(IdempotencyOutcome::Applied)
+        );
+        assert_eq!(
+            bank.process_transaction_with_idempotency(deposit, "a"),
+            Ok(IdempotencyOutcome::AlreadySeen)
+        );
+        assert_eq!(bank.accounts[&1].available(), 10.0.into());
+
+        // Failed transactions can be retried with the same key.
+        assert!(bank
+            .process_transaction_with_idempotency(withdrawal.clone(), "b")
+            .is_err());
+        bank.process_deposit_raw(1, 3, 10.0.into()).unwrap();
+        assert_eq!(
+            bank.process_transaction_with_idempotency(withdrawal, "b"),
+            Ok(IdempotencyOutcome::Applied)
+        );
+        assert_eq!(bank.accounts[&1].available(), Amount::new());
+    }
+
+    #[test]
+    fn idempotency_cache() {
+        let mut bank = Bank::with_config(BankConfig {
+            max_idempotency_cache: Some(2),
+            ..BankConfig::default()
+        });
+        let deposit = |tx| Transaction::new(Kind::Deposit, 1, tx, Some(1.0.into()));
+
+        bank.process_transaction_with_idempotency(deposit(1), "a")
+            .unwrap();
+        bank.process_transaction_with_idempotency(deposit(2), "b")
+            .unwrap();
+        bank.process_transaction_with_idempotency(deposit(3), "c")
+            .unwrap();
+
+        // "a" was evicted, "c" is still remembered.
+        assert_eq!(
+            bank.process_transaction_with_idempotency(deposit(4), "a"),
+            Ok(IdempotencyOutcome::Applied)
+        );
+        assert_eq!(
+            bank.process_transaction_with_idempotency(deposit(5), "c"),
+            Ok(IdempotencyOutcome::AlreadySeen)
+        );
+
+        bank.flush_idempotency_cache();
+        assert_eq!(
+            bank.process_transaction_with_idempotency(deposit(6), "c"),
+            Ok(IdempotencyOutcome::Applied)
+        );
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+    }
+
+    #[test]
+    fn net_change_since_deposit() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 3.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 5.0.into()).unwrap();
+        assert_eq!(bank.net_change_since_deposit(1, 1), Some(2.0.into()));
+
+        // Disputes move funds to held, leaving the total unchanged.
+        bank.process_dispute_raw(1, 3).unwrap();
+        assert_eq!(bank.net_change_since_deposit(1, 1), Some(2.0.into()));
+        assert_eq!(bank.net_change_since_deposit(1, 3), Some(0.into()));
+        bank.process_chargeback_raw(1, 3).unwrap();
+        assert_eq!(bank.net_change_since_deposit(1, 1), Some((-3.0).into()));
+
+        assert_eq!(bank.net_change_since_deposit(1, 2), None);
+        assert_eq!(bank.net_change_since_deposit(1, 4), None);
+        assert_eq!(bank.net_change_since_deposit(2, 1), None);
+    }
+
+    #[test]
+    fn export_transactions_csv() {
+        let input = "\
+type,client,tx,amount
+deposit,1,1,10.0
+deposit,2,2,5.0
+withdrawal,1,3,2.5
+deposit,1,4,1.25
+dispute,1,4,
+";
+        let mut bank = Bank::new();
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        for transaction in reader.deserialize() {
+            bank.process_transaction(transaction.unwrap()).unwrap();
+        }
+
+        let mut output = Vec::new();
+        bank.export_transactions_csv(1, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,3,2.5
+deposit,1,4,1.25
+"
+        );
+    }
+
+    #[test]
+    fn export_audit_trail_csv() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        clock.advance(Duration::from_millis(1_500));
+        bank.process_transaction_with_metadata(
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())),
+            HashMap::from([
+                ("source".to_string(), "webhook".to_string()),
+                ("operator".to_string(), "alice".to_string()),
+            ]),
+        )
+        .unwrap();
+        clock.advance(Duration::from_secs(2));
+        bank.process_withdrawal_raw(1, 2, 40.0.into()).unwrap_err();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_transaction_dry_run(&Transaction::new(Kind::Resolve, 1, 1, None))
+            .unwrap();
+
+        let mut output = Vec::new();
+        bank.export_audit_trail_csv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 1 + bank.audit_trail().len());
+        assert_eq!(
+            output,
+            format!(
+                "\
+timestamp,tx_id,client,kind,amount,outcome,metadata
+1500,1,1,deposit,10.0,ok,\"{{\"\"operator\"\":\"\"alice\"\",\"\"source\"\":\"\"webhook\"\"}}\"
+3500,2,1,withdrawal,40.0,{},{{}}
+3500,1,1,dispute,,ok,{{}}
+3500,1,1,resolve,,\"dry run, would succeed\",{{}}
+",
+                BankError::Account(AccountError::InsufficientFunds)
+            )
+        );
+    }
+
+    #[test]
+    fn import_audit_trail_csv() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        bank.process_transaction_with_metadata(
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())),
+            HashMap::from([("source".to_string(), "webhook".to_string())]),
+        )
+        .unwrap();
+        clock.advance(Duration::from_millis(250));
+        bank.process_withdrawal_raw(1, 2, 40.0.into()).unwrap_err();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_transaction_dry_run(&Transaction::new(Kind::Chargeback, 1, 1, None))
+            .unwrap();
+
+        let mut output = Vec::new();
+        bank.export_audit_trail_csv(&mut output).unwrap();
+        let entries = Bank::import_audit_trail_csv(output.as_slice()).unwrap();
+
+        assert_eq!(entries.len(), bank.audit_trail().len());
+        for (imported, original) in entries.iter().zip(bank.audit_trail()) {
+            assert_eq!(imported.timestamp(), original.timestamp());
+            assert_eq!(imported.transaction().id(), original.transaction().id());
+            assert_eq!(
+                imported.transaction().client(),
+                original.transaction().client()
+            );
+            assert_eq!(imported.transaction().kind(), original.transaction().kind());
+            assert_eq!(
+                imported.transaction().amount(),
+                original.transaction().amount()
+            );
+            assert_eq!(imported.metadata(), original.metadata());
+        }
+        assert_eq!(entries[0].outcome(), &AuditOutcome::Ok);
+        assert_eq!(
+            entries[1].outcome(),
+            &AuditOutcome::ArchivedErr(
+                BankError::Account(AccountError::InsufficientFunds).to_string()
+            )
+        );
+        assert!(entries[1].outcome().is_err());
+        assert!(entries[3].is_dry_run());
+
+        let mut exported_again = Vec::new();
+        Bank::replay_from_audit_trail(&entries, BankConfig::default())
+            .unwrap()
+            .export_csv(&mut exported_again)
+            .unwrap();
+        let mut expected = Vec::new();
+        bank.export_csv(&mut expected).unwrap();
+        assert_eq!(exported_again, expected);
+
+        let header = "timestamp,tx_id,client,kind,amount,outcome,metadata\n";
+        let cases = [
+            (
+                "-5,1,1,deposit,1.0,ok,{}\n",
+                ImportError::MalformedRow { line: 2 },
+            ),
+            (
+                "0,1,1,transfer,1.0,ok,{}\n",
+                ImportError::MalformedRow { line: 2 },
+            ),
+            (
+                "0,1,1,deposit,1.0,ok,nope\n",
+                ImportError::MalformedRow { line: 2 },
+            ),
+            (
+                "0,1,1,deposit,1.0,,{}\n",
+                ImportError::MalformedRow { line: 2 },
+            ),
+            (
+                "0,1,1,deposit,1.0,ok\n",
+                ImportError::MalformedRow { line: 2 },
+            ),
+            (
+                "0,1,1,deposit,abc,ok,{}\n",
+                ImportError::InvalidAmount { line: 2 },
+            ),
+        ];
+        for (rows, err) in cases {
+            let csv = format!("{}{}", header, rows);
+            assert_eq!(
+                Bank::import_audit_trail_csv(csv.as_bytes()).map(|e| e.len()),
+                Err(err)
+            );
+        }
+    }
+
+    #[test]
+    fn export_disputes_csv() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 1.0.into()).unwrap();
+
+        bank.process_dispute_raw(2, 3).unwrap();
+        clock.advance(Duration::from_secs(90));
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_resolve_partial(1, 2.5.into()).unwrap();
+        clock.advance(Duration::from_millis(10_500));
+
+        let mut output = Vec::new();
+        bank.export_disputes_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+tx_id,client,amount,opened_at
+1,1,7.5,90000
+3,2,5.0,0
+"
+        );
+    }
+
+    #[test]
+    fn import_disputes_from_csv() {
+        let clock = Arc::new(MockClock::new());
+        let mut bank = Bank::with_config(BankConfig {
+            clock: clock.clone(),
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        clock.advance(Duration::from_secs(30));
+        bank.process_dispute_raw(2, 3).unwrap();
+        bank.process_resolve_partial(3, 1.0.into()).unwrap();
+        clock.advance(Duration::from_secs(5));
+
+        let mut exported = Vec::new();
+        bank.export_disputes_csv(&mut exported).unwrap();
+        let summary = |bank: &Bank| {
+            let mut disputes: Vec<_> = bank
+                .disputes
+                .iter()
+                .map(|(id, t)| (*id, t.id(), t.client(), t.kind(), t.amount()))
+                .collect();
+            disputes.sort_unstable_by_key(|d| d.0);
+            disputes
+        };
+        let disputes = summary(&bank);
+        bank.disputes.clear();
+        bank.dispute_opened_at.clear();
+
+        bank.import_disputes_from_csv(exported.as_slice()).unwrap();
+        assert_eq!(summary(&bank), disputes);
+        assert_eq!(bank.oldest_dispute(), Some((1, Duration::from_secs(35))));
+        assert_eq!(bank.validate_consistency(), Ok(()));
+
+        let mut reexported = Vec::new();
+        bank.export_disputes_csv(&mut reexported).unwrap();
+        assert_eq!(reexported, exported);
+    }
+
+    #[test]
+    fn import_disputes_from_csv_errors() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 1.0.into()).unwrap();
+
+        let header = "tx_id,client,amount,opened_at\n";
+        let cases = [
+            ("1,1,5.0\n", ImportError::MalformedRow { line: 2 }),
+            ("1,1,5.0,-1\n", ImportError::MalformedRow { line: 2 }),
+            ("1,1,abc,0\n", ImportError::InvalidAmount { line: 2 }),
+            (
+                "1,1,5.0,0\n1,1,11.0,0\n",
+                ImportError::InvalidAmount { line: 3 },
+            ),
+            ("1,2,5.0,0\n", ImportError::UnknownTransaction(1)),
+            ("2,1,1.0,0\n", ImportError::UnknownTransaction(2)),
+            ("3,1,1.0,0\n", ImportError::UnknownTransaction(3)),
+        ];
+        for (rows, error) in cases {
+            let input = format!("{}{}", header, rows);
+            assert_eq!(bank.import_disputes_from_csv(input.as_bytes()), Err(error));
+        }
+        assert!(bank.disputes.is_empty());
+        assert!(bank.dispute_opened_at.is_empty());
+    }
+
+    #[test]
+    fn write_summary() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(2, 2, 2.5.into()).unwrap();
+
+        let mut output = Vec::new();
+        bank.write_summary(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+accounts: 2
+locked accounts: 0
+open disputes: 1
+total assets: 12.5000
+total held: 10.0000
+transactions: 2
+"
+        );
+    }
+
+    #[test]
+    fn import_state_from_accounts_csv() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 2.5.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 7.1234.into()).unwrap();
+        bank.process_deposit_raw(3, 4, 1.0.into()).unwrap();
+        bank.process_dispute_raw(3, 4).unwrap();
+        bank.process_chargeback_raw(3, 4).unwrap();
+
+        let mut csv = Vec::new();
+        bank.export_csv(&mut csv).unwrap();
+        let imported = Bank::import_state_from_accounts_csv(csv.as_slice()).unwrap();
+
+        assert_eq!(
+            imported.accounts_iter_sorted_by_id().collect::<Vec<_>>(),
+            bank.accounts_iter_sorted_by_id().collect::<Vec<_>>()
+        );
+        assert!(imported.transactions.is_empty());
+        assert!(imported.disputes.is_empty());
+    }
+
+    #[test]
+    fn import_state_from_accounts_csv_errors() {
+        let import = |rows: &str| {
+            let csv = format!("client,available,held,total,locked\n{}", rows);
+            Bank::import_state_from_accounts_csv(csv.as_bytes()).map(|_| ())
+        };
+
+        assert_eq!(import("1,1.0,0.0,1.0,false\n"), Ok(()));
+        assert_eq!(
+            import("1,1.0,0.0,1.0\n"),
+            Err(ImportError::MalformedRow { line: 2 })
+        );
+        assert_eq!(
+            import("1,1.0,0.0,1.0,false\nx,1.0,0.0,1.0,false\n"),
+            Err(ImportError::MalformedRow { line: 3 })
+        );
+        assert_eq!(
+            import("1,1.0,0.0,1.0,maybe\n"),
+            Err(ImportError::MalformedRow { line: 2 })
+        );
+        assert_eq!(
+            import("1,abc,0.0,1.0,false\n"),
+            Err(ImportError::InvalidAmount { line: 2 })
+        );
+        assert_eq!(
+            import("1,1.0,0.5,1.0,false\n"),
+            Err(ImportError::InvalidAmount { line: 2 })
+        );
+        assert_eq!(
+            import("1,1.0,0.0,1.0,false\n1,2.0,0.0,2.0,false\n"),
+            Err(ImportError::DuplicateClient(1))
+        );
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut bank = Bank::with_capacity(10, 100);
+        assert!(bank.accounts.capacity() >= 10);
+        assert!(bank.transactions.capacity() >= 100);
+        assert!(bank.disputes.capacity() >= 100);
+
+        let mut default = Bank::new();
+        for b in [&mut bank, &mut default] {
+            b.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+            b.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+            b.process_withdrawal_raw(1, 3, 2.0.into()).unwrap();
+            b.process_dispute_raw(2, 2).unwrap();
+            b.process_resolve_raw(1, 2).unwrap_err();
+        }
+        assert_eq!(bank.diff(&default), BankDiff::default());
+        assert_eq!(
+            bank.count_disputes_per_account(),
+            default.count_disputes_per_account()
+        );
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut bank = Bank::new();
+        for client in 0..1000 {
+            bank.process_deposit_raw(client, client.into(), 1.0.into())
+                .unwrap();
+        }
+        bank.accounts.retain(|id, _| id % 2 == 0);
+        bank.transactions.retain(|id, _| id % 2 == 0);
+
+        let accounts = bank.accounts.capacity();
+        let transactions = bank.transactions.capacity();
+        bank.shrink_to_fit();
+        assert!(bank.accounts.capacity() < accounts);
+        assert!(bank.transactions.capacity() < transactions);
+        assert!(bank.accounts.capacity() >= 500);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut bank = disputed_bank();
+        bank.reserve(500, 500);
+        let accounts = bank.accounts.capacity();
+        let transactions = bank.transactions.capacity();
+
+        for client in 2..502 {
+            bank.process_deposit_raw(client, client.into(), 1.0.into())
+                .unwrap();
+        }
+        assert_eq!(bank.accounts.capacity(), accounts);
+        assert_eq!(bank.transactions.capacity(), transactions);
+    }
+
+    #[test]
+    fn client_transaction_ids() {
+        let mut indexed = Bank::with_config(BankConfig {
+            enable_client_index: true,
+            ..BankConfig::default()
+        });
+        let mut bank = Bank::new();
+        for b in [&mut indexed, &mut bank] {
+            b.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+            b.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+            b.process_withdrawal_raw(1, 3, 5.0.into()).unwrap();
+            b.process_deposit_raw(3, 4, 10.0.into()).unwrap();
+            // Reuses the id of client 2's deposit.
+            b.process_deposit_raw(3, 2, 10.0.into()).unwrap();
+        }
+
+        for client in 1..=3 {
+            let mut expected: Vec<_> = bank.transaction_ids_for_client(client).collect();
+            expected.sort_unstable();
+            let mut ids = indexed
+                .client_transaction_ids(client)
+                .unwrap_or_default()
+                .to_vec();
+            ids.sort_unstable();
+            assert_eq!(ids, expected);
+        }
+        assert_eq!(indexed.client_transaction_ids(3), Some(&[4, 2][..]));
+        assert_eq!(bank.client_transaction_ids(1), None);
+
+        indexed.merge_accounts(1, 3).unwrap();
+        assert_eq!(indexed.client_transaction_ids(1), None);
+        assert_eq!(indexed.client_transaction_ids(3), Some(&[4, 2, 1, 3][..]));
+    }
+
+    #[test]
+    fn compact_transactions() {
+        let mut bank = Bank::with_config(BankConfig {
+            enable_client_index: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 2, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 2.0.into()).unwrap();
+        bank.process_deposit_raw(2, 5, 3.0.into()).unwrap();
+        bank.process_deposit_raw(2, 6, 4.0.into()).unwrap();
+        bank.process_dispute_raw(2, 5).unwrap();
+        bank.process_withdrawal_raw(2, 7, 3.0.into()).unwrap();
+        bank.process_deposit_raw(3, 8, 1.0.into()).unwrap();
+        bank.process_withdrawal_raw(3, 9, 1.0.into()).unwrap();
+        bank.process_transaction(Transaction::new(Kind::AdminReversal, 3, 9, None))
+            .unwrap();
+        let balances: Vec<_> = bank.accounts_iter_sorted_by_id().collect();
+
+        assert_eq!(bank.compact_transactions(), 1);
+        assert_eq!(
+            bank.accounts_iter_sorted_by_id().collect::<Vec<_>>(),
+            balances
+        );
+        assert_eq!(
+            bank.process_dispute_raw(1, 1),
+            Err(BankError::TransactionNotFound(1))
+        );
+        assert_eq!(bank.client_transaction_ids(1), Some(&[3, 4][..]));
+        assert_eq!(bank.transactions.len(), 7);
+        assert_eq!(bank.compact_transactions(), 0);
+    }
+
+    #[test]
+    fn describe_transaction() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_audit_trail: true,
+            ..BankConfig::default()
+        });
+        bank.process_transaction(
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())).with_memo("payroll"),
+        )
+        .unwrap();
+        for tx in 2..=4 {
+            bank.process_deposit_raw(1, tx, 1.0.into()).unwrap();
+        }
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_dispute_raw(1, 3).unwrap();
+        bank.process_resolve_raw(1, 3).unwrap();
+        bank.process_dispute_raw(1, 4).unwrap();
+        bank.process_chargeback_raw(1, 4).unwrap();
+        bank.process_withdrawal_raw(1, 5, 100.0.into()).unwrap_err();
+
+        let status = |tx| bank.describe_transaction(tx).map(|d| d.status);
+        assert_eq!(status(1), Some(TransactionStatus::Settled));
+        assert_eq!(status(2), Some(TransactionStatus::InDispute));
+        assert_eq!(status(3), Some(TransactionStatus::Resolved));
+        assert_eq!(status(4), Some(TransactionStatus::ChargedBack));
+        assert_eq!(status(5), Some(TransactionStatus::NotFound));
+        assert_eq!(status(6), None);
+
+        assert_eq!(
+            bank.describe_transaction(1),
+            Some(TransactionDescription {
+                id: 1,
+                client: 1,
+                kind: Kind::Deposit,
+                amount: Some(10.0.into()),
+                status: TransactionStatus::Settled,
+                memo: Some("payroll".to_string()),
+            })
+        );
+        assert_eq!(bank.describe_transaction(5).unwrap().kind, Kind::Withdrawal);
+    }
+
+    #[test]
+    fn account_statement_csv() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            ..BankConfig::default()
+        });
+        bank.process_transaction(
+            Transaction::new(Kind::Deposit, 1, 1, Some(10.0.into())).with_memo("payroll"),
+        )
+        .unwrap();
+        bank.process_deposit_raw(2, 2, 3.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 2.5.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 50.0.into()).unwrap_err();
+        bank.process_dispute_raw(1, 1).unwrap_err();
+        bank.process_deposit_raw(1, 5, 1.0.into()).unwrap();
+        bank.process_dispute_raw(1, 5).unwrap();
+        bank.process_chargeback_raw(1, 5).unwrap();
+
+        let mut output = Vec::new();
+        bank.account_statement_csv(1, &mut output).unwrap();
+
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["tx_id", "kind", "amount", "balance_after", "memo"]
+        );
+        type Row = (
+            TransactionID,
+            String,
+            Option<Amount>,
+            Amount,
+            Option<String>,
+        );
+        let rows: Vec<Row> = reader.deserialize().map(Result::unwrap).collect();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    1,
+                    "deposit".into(),
+                    Some(10.0.into()),
+                    10.0.into(),
+                    Some("payroll".into())
+                ),
+                (3, "withdrawal".into(), Some(2.5.into()), 7.5.into(), None),
+                (5, "deposit".into(), Some(1.0.into()), 8.5.into(), None),
+                (5, "dispute".into(), None, 8.5.into(), None),
+                (5, "chargeback".into(), None, 7.5.into(), None),
+            ]
+        );
+        assert_eq!(bank.account_statement(2).len(), 1);
+        assert!(bank.account_statement(3).is_empty());
+    }
+
+    #[test]
+    fn accounts_iter_with_account() {
+        let mut bank = disputed_bank();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        bank.process_deposit_raw(2, 2, 1.0.into()).unwrap();
+
+        let mut chargebacks: Vec<_> = bank
+            .accounts_iter_with_account()
+            .map(|(id, account)| (id, account.chargeback_count()))
+            .collect();
+        chargebacks.sort_unstable();
+        assert_eq!(chargebacks, vec![(1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn accounts_iter_mut() {
+        let mut bank = Bank::new();
+        for client in 1..=3 {
+            bank.process_deposit_raw(client, client.into(), 10.0.into())
+                .unwrap();
+        }
+
+        let fee: Amount = 1.5.into();
+        for (_, account) in bank.accounts_iter_mut() {
+            account.try_debit(fee).unwrap();
+        }
+        assert!(bank
+            .accounts_iter()
+            .all(|(_, available, _, _, _)| available == 8.5.into()));
+    }
+
+    #[test]
+    fn retain_accounts() {
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 1.0.into()).unwrap();
+        bank.process_chargeback_raw(1, 1).unwrap();
+        bank.process_deposit_raw(2, 3, 5.0.into()).unwrap();
+        bank.process_deposit_raw(3, 4, 5.0.into()).unwrap();
+        bank.process_dispute_raw(3, 4).unwrap();
+        let child = bank.create_sub_account(1).unwrap();
+        let grandchild = bank.create_sub_account(child).unwrap();
+
+        bank.retain_accounts(|_, account| !account.is_locked());
+
+        assert!(bank.get_account(1).is_none());
+        assert_eq!(bank.transaction_ids_for_client(1).count(), 0);
+        assert!(bank.account_statement(1).is_empty());
+        assert_eq!(bank.accounts[&child].parent_id(), None);
+        assert_eq!(bank.accounts[&grandchild].parent_id(), Some(child));
+        assert_eq!(bank.accounts.len(), 4);
+        assert_eq!(bank.transactions.len(), 2);
+        assert_eq!(bank.count_disputes_per_account(), HashMap::from([(3, 1)]));
+        assert_eq!(bank.validate_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn pop_account() {
+        let mut bank = Bank::with_config(BankConfig {
+            record_statements: true,
+            enable_client_index: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(1, 3, 4.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 2.0.into()).unwrap();
+        bank.process_dispute_raw(1, 3).unwrap();
+        bank.process_dispute_raw(2, 2).unwrap();
+        let child = bank.create_sub_account(1).unwrap();
+
+        assert!(bank.pop_account(9).is_none());
+        let (account, transactions) = bank.pop_account(1).unwrap();
+        assert_eq!(account.total(), 12.0.into());
+        assert_eq!(account.held(), 4.0.into());
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|t| (t.id(), t.kind(), t.amount()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, Kind::Deposit, Some(10.0.into())),
+                (3, Kind::Deposit, Some(4.0.into())),
+                (4, Kind::Withdrawal, Some(2.0.into())),
+            ]
+        );
+
+        assert!(!bank.contains_account(1));
+        assert!(bank.transactions.values().all(|t| t.client() != 1));
+        assert!(bank.disputes.values().all(|t| t.client() != 1));
+        assert!(!bank.dispute_opened_at.contains_key(&3));
+        assert!(!bank.client_transactions.contains_key(&1));
+        assert!(!bank.statements.contains_key(&1));
+        assert_eq!(bank.accounts[&child].parent_id(), None);
+        assert!(bank.disputes.contains_key(&2));
+        assert_eq!(bank.validate_consistency(), Ok(()));
+        assert!(bank.pop_account(1).is_none());
+    }
+
+    #[test]
+    fn count_transactions_for_client() {
+        let mut indexed = Bank::with_config(BankConfig {
+            enable_client_index: true,
+            ..BankConfig::default()
+        });
+        let mut bank = Bank::new();
+        for b in [&mut indexed, &mut bank] {
+            b.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+            b.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+            b.process_withdrawal_raw(2, 3, 1.0.into()).unwrap();
+            b.process_deposit_raw(2, 4, 1.0.into()).unwrap();
+            b.process_dispute_raw(2, 4).unwrap();
+        }
+
+        for b in [&indexed, &bank] {
+            assert_eq!(b.count_transactions_for_client(3), 0);
+            assert_eq!(b.count_transactions_for_client(1), 1);
+            assert_eq!(b.count_transactions_for_client(2), 3);
+        }
+    }
+
+    #[test]
+    fn totals_for_client() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 2.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 4, 1.5.into()).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_deposit_raw(2, 5, 100.0.into()).unwrap();
+
+        assert_eq!(bank.deposit_total_for_client(1), 15.0.into());
+        assert_eq!(bank.withdrawal_total_for_client(1), 3.5.into());
+        assert_eq!(bank.withdrawal_total_for_client(2), Amount::new());
+        assert_eq!(bank.deposit_total_for_client(3), Amount::new());
+    }
+
+    #[test]
+    fn import_transactions_csv() {
+        let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+withdrawal, 1, 2, 20.0
+dispute, 1, 1
+";
+        let mut bank = Bank::new();
+        let result = bank
+            .import_transactions_csv(std::io::Cursor::new(input))
+            .unwrap();
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(
+            result.failed,
+            vec![(2, BankError::Account(AccountError::InsufficientFunds))]
+        );
+        assert_eq!(bank.accounts[&1].held(), 10.0.into());
+
+        let input = "type,client,tx,amount\ndeposit,1,3,1.0\nbogus,1,4,1.0\n";
+        assert!(bank
+            .import_transactions_csv(std::io::Cursor::new(input))
+            .is_err());
+        assert_eq!(bank.accounts[&1].total(), 11.0.into());
+    }
+
+    #[test]
+    fn process_reversals_from_csv() {
+        let mut bank = Bank::new();
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_deposit_raw(2, 3, 8.0.into()).unwrap();
+
+        let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10.0
+withdrawal, 2, 3, 8.0
+dispute, 1, 1
+admin_reversal, 1, 7
+";
+        let result = bank
+            .process_reversals_from_csv(std::io::Cursor::new(input))
+            .unwrap();
+        assert_eq!(result.succeeded, 2);
+        assert_eq!(
+            result.failed,
+            vec![
+                (1, BankError::AlreadyReversed(1)),
+                (7, BankError::TransactionNotFound(7)),
+            ]
+        );
+        assert_eq!(bank.accounts[&1].available(), 5.0.into());
+        assert_eq!(bank.accounts[&2].available(), 0.into());
+    }
+
+    #[test]
+    fn transaction_limit() {
+        let mut bank = Bank::with_transaction_limit(3);
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(2, 2, 10.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 1.0.into()).unwrap();
+
+        let before = bank.clone();
+        assert_eq!(
+            bank.process_deposit_raw(3, 4, 1.0.into()),
+            Err(BankError::StorageFull)
+        );
+        assert_eq!(
+            bank.process_withdrawal_raw(2, 5, 1.0.into()),
+            Err(BankError::StorageFull)
+        );
+        assert_eq!(bank.diff(&before), BankDiff::default());
+        assert!(bank.get_account(3).is_none());
+
+        // Transactions which are not recorded are still applied.
+        bank.process_dispute_raw(2, 2).unwrap();
+    }
+
+    #[test]
+    fn adjustment() {
+        let adjust =
+            |tx, amount: f64| Transaction::new(Kind::Adjustment, 1, tx, Some(amount.into()));
+
+        let mut bank = disputed_bank();
+        assert_eq!(
+            bank.process_transaction(adjust(2, 1.0)),
+            Err(BankError::AdjustmentsDisabled)
+        );
+
+        let mut bank = Bank::with_config(BankConfig {
+            allow_adjustments: true,
+            ..BankConfig::default()
+        });
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+
+        for locked in [false, true] {
+            if locked {
+                bank.process_dispute_raw(1, 2).unwrap();
+                bank.process_chargeback_raw(1, 2).unwrap();
+            }
+            assert_eq!(bank.accounts[&1].is_locked(), locked);
+            let available = bank.accounts[&1].available();
+
+            bank.process_transaction(adjust(3, 2.5)).unwrap();
+            assert_eq!(bank.accounts[&1].available(), available + 2.5.into());
+            bank.process_transaction(adjust(4, -4.0)).unwrap();
+            assert_eq!(bank.accounts[&1].available(), available - 1.5.into());
+            bank.process_transaction(adjust(5, 0.0)).unwrap();
+            assert_eq!(bank.accounts[&1].available(), available - 1.5.into());
+            assert_eq!(
+                bank.process_transaction(adjust(6, -100.0)),
+                Err(BankError::Account(AccountError::InsufficientFunds))
+            );
+        }
+        assert_eq!(
+            bank.process_transaction(Transaction::new(Kind::Adjustment, 1, 7, None)),
+            Err(BankError::MissingAmount)
+        );
+        assert_eq!(bank.reconcile(), Ok(()));
+    }
+
+    type Event = (&'static str, AccountID, TransactionID, Amount);
+
+    // Records every event it is notified of.
+    #[derive(Clone, Default)]
+    struct MockHandler {
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl MockHandler {
+        fn record(
+            &self,
+            event: &'static str,
+            client: AccountID,
+            tx: TransactionID,
+            amount: Amount,
+        ) {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event, client, tx, amount));
+        }
+
+        fn events(&self) -> Vec<Event> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    impl TransactionEventHandler for MockHandler {
+        fn on_deposit(&self, client: AccountID, tx_id: TransactionID, amount: Amount) {
+            self.record("deposit", client, tx_id, amount);
+        }
+        fn on_withdrawal(&self, client: AccountID, tx_id: TransactionID, amount: Amount) {
+            self.record("withdrawal", client, tx_id, amount);
+        }
+        fn on_dispute(&self, client: AccountID, tx_id: TransactionID, amount: Amount) {
+            self.record("dispute", client, tx_id, amount);
+        }
+        fn on_resolve(&self, client: AccountID, tx_id: TransactionID, amount: Amount) {
+            self.record("resolve", client, tx_id, amount);
+        }
+        fn on_chargeback(&self, client: AccountID, tx_id: TransactionID, amount: Amount) {
+            self.record("chargeback", client, tx_id, amount);
+        }
+    }
+
+    #[test]
+    fn subscribe() {
+        let handler = MockHandler::default();
+        let mut bank = Bank::new();
+        bank.subscribe(Box::new(handler.clone()));
+
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+        bank.process_deposit_raw(1, 2, 5.0.into()).unwrap();
+        bank.process_withdrawal_raw(1, 3, 2.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap();
+        bank.process_resolve_raw(1, 1).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.process_chargeback_raw(1, 2).unwrap();
+
+        // Failed operations are not notified.
+        assert!(bank.process_withdrawal_raw(2, 4, 1.0.into()).is_err());
+        assert!(bank.process_resolve_raw(1, 1).is_err());
+
+        assert_eq!(
+            handler.events(),
+            vec![
+                ("deposit", 1, 1, 10.0.into()),
+                ("deposit", 1, 2, 5.0.into()),
+                ("withdrawal", 1, 3, 2.0.into()),
+                ("dispute", 1, 1, 10.0.into()),
+                ("resolve", 1, 1, 10.0.into()),
+                ("dispute", 1, 2, 5.0.into()),
+                ("chargeback", 1, 2, 5.0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsubscribe() {
+        let first = MockHandler::default();
+        let second = MockHandler::default();
+        let mut bank = Bank::new();
+        let first_id = bank.subscribe(Box::new(first.clone()));
+        let second_id = bank.subscribe(Box::new(second.clone()));
+        assert_ne!(first_id, second_id);
+
+        assert!(bank.unsubscribe(first_id));
+        assert!(!bank.unsubscribe(first_id));
+        bank.process_deposit_raw(1, 1, 10.0.into()).unwrap();
+
+        assert!(first.events().is_empty());
+        assert_eq!(second.events(), vec![("deposit", 1, 1, 10.0.into())]);
+        assert_ne!(bank.subscribe(Box::new(first.clone())), first_id);
+    }
+
+    #[test]
+    fn subscribe_admin_resolves() {
+        let handler = MockHandler::default();
+        let mut bank = disputed_bank();
+        bank.process_deposit_raw(1, 2, 4.0.into()).unwrap();
+        bank.process_dispute_raw(1, 2).unwrap();
+        bank.subscribe(Box::new(handler.clone()));
+
+        bank.process_resolve_partial(1, 3.0.into()).unwrap();
+        bank.process_dispute_raw(1, 1).unwrap_err();
+        assert_eq!(bank.force_resolve_all_disputes().resolved, vec![1, 2]);
+
+        let events = handler.events();
+        assert_eq!(
+            events,
+            vec![
+                ("resolve", 1, 1, 3.0.into()),
+                ("resolve", 1, 1, 7.0.into()),
+                ("resolve", 1, 2, 4.0.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn subscribe_not_cloned() {
+        let handler = MockHandler::default();
+        let mut bank = disputed_bank();
+        bank.subscribe(Box::new(handler.clone()));
+
+        let deposit = Transaction::new(Kind::Deposit, 1, 2, Some(5.0.into()));
+        bank.simulate_transaction(&deposit).unwrap();
+        bank.clone().process_transaction(deposit).unwrap();
+        assert!(handler.events().is_empty());
+    }
+
+    #[test]
+    fn diff_deposit() {
+        let mut before = Bank::new();
+        before
+            .process_transaction(Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())))
+            .unwrap();
+
+        let mut after = Bank::new();
+        after
+            .process_transaction(Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())))
+            .unwrap();
+        after
+            .process_transaction(Transaction::new(Kind::Deposit, 1, 2, Some(2.0.into())))
+            .unwrap();
+        after
+            .process_transaction(Transaction::new(Kind::Deposit, 2, 3, Some(3.0.into())))
+            .unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.new_accounts, vec![2]);
+        assert!(diff.removed_accounts.is_empty());
+        assert_eq!(diff.modified_accounts.len(), 1);
+        assert_eq!(diff.modified_accounts[0].0, 1);
+        assert_eq!(diff.modified_accounts[0].1.available(), 1.0.into());
+        assert_eq!(diff.modified_accounts[0].2.available(), 3.0.into());
+        assert_eq!(diff.new_transactions, vec![2, 3]);
+        assert!(diff.resolved_disputes.is_empty());
+
+        let diff = after.diff(&before);
+        assert!(diff.new_accounts.is_empty());
+        assert_eq!(diff.removed_accounts, vec![2]);
+        assert!(diff.new_transactions.is_empty());
+    }
+
+    #[test]
+    fn diff_dispute_resolve() {
+        let mut before = Bank::new();
+        before
+            .process_transaction(Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())))
+            .unwrap();
+        before
+            .process_transaction(Transaction::new(Kind::Dispute, 1, 1, None))
+            .unwrap();
+
+        let mut after = Bank::new();
+        after
+            .process_transaction(Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())))
+            .unwrap();
+        after
+            .process_transaction(Transaction::new(Kind::Dispute, 1, 1, None))
+            .unwrap();
+        after
+            .process_transaction(Transaction::new(Kind::Resolve, 1, 1, None))
+            .unwrap();
+
+        let diff = before.diff(&after);
+        assert!(diff.new_accounts.is_empty());
+        assert!(diff.new_transactions.is_empty());
+        assert_eq!(diff.resolved_disputes, vec![1]);
+        assert_eq!(diff.modified_accounts.len(), 1);
+        assert_eq!(diff.modified_accounts[0].1.held(), 1.0.into());
+        assert_eq!(diff.modified_accounts[0].2.held(), 0.into());
+
+        assert_eq!(after.diff(&after), BankDiff::default());
     }
 }
@@ -0,0 +1,114 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    amount::Amount,
+    clock::{Clock, SystemClock},
+};
+
+/// Configuration for how a `Bank` operates. The default configuration matches the behaviour of a
+/// plain `Bank::new()`.
+#[derive(Clone, Debug)]
+pub struct BankConfig {
+    // Rules used by `Bank::accounts_needing_review`.
+    pub review_thresholds: ReviewThresholds,
+
+    // How many of the most recent transactions are kept for rolling statistics such as
+    // `Bank::transaction_volume`.
+    pub recent_transactions: usize,
+
+    // Record every transaction passed to `Bank::process_transaction` in an audit trail. Off by
+    // default, since it keeps a copy of every transaction in memory.
+    pub record_audit_trail: bool,
+
+    // Keep a statement of every transaction applied to each account, for
+    // `Bank::account_statement`. Off by default, since it keeps a copy of every transaction in
+    // memory.
+    pub record_statements: bool,
+
+    // How many keys `Bank::process_transaction_with_idempotency` remembers, evicting the least
+    // recently used. At least one key is always kept. Unbounded if `None`.
+    pub max_idempotency_cache: Option<usize>,
+
+    // Maintain an index of transaction ids by client for `Bank::client_transaction_ids`. Off by
+    // default, since it keeps a second copy of every id in memory.
+    pub enable_client_index: bool,
+
+    // How many deposits and withdrawals may be recorded. Further deposits and withdrawals are
+    // rejected with `BankError::StorageFull`. Unbounded if `None`.
+    pub max_transactions: Option<usize>,
+
+    // Apply `Kind::Adjustment` transactions. Off by default, since adjustments bypass the usual
+    // rules for locked accounts.
+    pub allow_adjustments: bool,
+
+    // Credited to every new account on its first deposit, as a separate deposit with a generated
+    // transaction id. No bonus if `None`.
+    pub initial_deposit_bonus: Option<Amount>,
+
+    // As `(max, window)`, reject transactions from clients which already have `max` of the last
+    // `window` applied transactions with `BankError::VelocityLimitExceeded`, see
+    // `Bank::transaction_velocity`. No limit if `None`.
+    pub max_velocity: Option<(u32, usize)>,
+
+    // Remember the kind of every transaction passed to `Bank::process_transaction`, for
+    // `Bank::list_all_kinds_seen`.
+    pub track_kinds_seen: bool,
+
+    // Source of the current time, e.g. for `Bank::oldest_dispute`.
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for BankConfig {
+    fn default() -> Self {
+        Self {
+            review_thresholds: ReviewThresholds::default(),
+            recent_transactions: 1000,
+            record_audit_trail: false,
+            record_statements: false,
+            max_idempotency_cache: None,
+            enable_client_index: false,
+            max_transactions: None,
+            allow_adjustments: false,
+            initial_deposit_bonus: None,
+            max_velocity: None,
+            track_kinds_seen: false,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// The operations applied by `Bank::process_end_of_period`, in the order they are applied. Nothing
+/// is applied by default.
+#[derive(Clone, Debug, Default)]
+pub struct PeriodConfig {
+    // Flat fee charged to every account.
+    pub maintenance_fee: Option<Amount>,
+
+    // Interest credited to every account, as a percentage of its available funds.
+    pub interest_rate: Option<f64>,
+
+    // Resolve disputes which have been open for at least this long.
+    pub expire_old_disputes: Option<Duration>,
+
+    // Flag every account returned by `Bank::accounts_needing_review` for review.
+    pub flag_suspicious_accounts: bool,
+}
+
+/// Thresholds above which an account is flagged for human review.
+#[derive(Clone, Debug)]
+pub struct ReviewThresholds {
+    // Accounts with more open disputes than this are flagged.
+    pub max_open_disputes: u32,
+
+    // Accounts holding more than this amount are flagged.
+    pub max_held: Amount,
+}
+
+impl Default for ReviewThresholds {
+    fn default() -> Self {
+        Self {
+            max_open_disputes: 3,
+            max_held: Amount::from_cents(1_000_000),
+        }
+    }
+}
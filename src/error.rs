@@ -1,5 +1,10 @@
 use std::{error::Error as StdError, fmt};
 
+use crate::{
+    amount::Amount,
+    bank::{AccountID, TransactionID},
+};
+
 /// An error type to wrap all errors that force us to terminate execution.
 #[derive(Debug)]
 pub struct Error {
@@ -40,3 +45,301 @@ impl From<csv::Error> for Error {
         Self::with_cause("CSV Error", other)
     }
 }
+
+impl From<std::io::Error> for Error {
+    fn from(other: std::io::Error) -> Self {
+        Self::with_cause("IO Error", other)
+    }
+}
+
+/// Errors returned when an operation on a single `Account` can not be applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountError {
+    /// The account does not have enough available funds for the operation.
+    InsufficientFunds,
+    /// The account does not have enough held funds for the operation.
+    InsufficientHeld,
+    /// The resulting balance would overflow the `Amount` representation.
+    OverflowError,
+    /// The account has been locked by a chargeback.
+    AccountLocked,
+    /// The account has been frozen by an administrator.
+    AccountFrozen,
+    /// The operation requires a positive amount.
+    NonPositiveAmount,
+    /// The operation does not accept a negative amount.
+    NegativeAmount,
+    /// The debit would take the available funds below the account's minimum balance.
+    MinimumBalanceViolation {
+        minimum: Amount,
+        would_result_in: Amount,
+    },
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientFunds => write!(f, "insufficient available funds"),
+            Self::InsufficientHeld => write!(f, "insufficient held funds"),
+            Self::OverflowError => write!(f, "balance overflow"),
+            Self::AccountLocked => write!(f, "account is locked"),
+            Self::AccountFrozen => write!(f, "account is frozen"),
+            Self::NonPositiveAmount => write!(f, "amount is not positive"),
+            Self::NegativeAmount => write!(f, "amount is negative"),
+            Self::MinimumBalanceViolation {
+                minimum,
+                would_result_in,
+            } => write!(
+                f,
+                "balance of {} would be below the minimum of {}",
+                would_result_in, minimum
+            ),
+        }
+    }
+}
+
+impl StdError for AccountError {}
+
+/// Errors returned when the `Bank` can not apply an operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BankError {
+    /// The transaction is missing an amount it requires.
+    MissingAmount,
+    /// The transaction amount is negative.
+    NegativeAmount,
+    /// No account exists for the given client.
+    AccountNotFound(AccountID),
+    /// Every `AccountID` is already in use.
+    AccountIdsExhausted,
+    /// The operation requires two different accounts.
+    SameAccount(AccountID),
+    /// The account has open disputes which prevent the operation.
+    OpenDisputes(AccountID),
+    /// No disputable transaction exists for the given id.
+    TransactionNotFound(TransactionID),
+    /// The transaction is already in dispute.
+    AlreadyDisputed(TransactionID),
+    /// The referenced transaction belongs to a different client.
+    ClientMismatch(TransactionID),
+    /// The referenced transaction is not a deposit, so it can not be disputed.
+    NotDisputable(TransactionID),
+    /// The referenced transaction has already been reversed.
+    AlreadyReversed(TransactionID),
+    /// There is no open dispute for the given transaction.
+    DisputeNotFound(TransactionID),
+    /// The requested amount is larger than the amount currently in dispute.
+    ExceedsDisputedAmount,
+    /// The withdrawal would take the account further below zero than its withdrawal limit allows.
+    ExceedsAccountLimit,
+    /// `BankConfig::max_transactions` transactions are already recorded.
+    StorageFull,
+    /// Adjustments are not enabled by `BankConfig::allow_adjustments`.
+    AdjustmentsDisabled,
+    /// A deposit or withdrawal with the same id is already recorded.
+    DuplicateTransaction(TransactionID),
+    /// The client already has as many recent transactions as `BankConfig::max_velocity` allows.
+    VelocityLimitExceeded(AccountID),
+    /// An account with the given id already exists.
+    DuplicateAccountId(AccountID),
+    /// The underlying account rejected the operation.
+    Account(AccountError),
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAmount => write!(f, "transaction is missing an amount"),
+            Self::NegativeAmount => write!(f, "transaction amount is negative"),
+            Self::AccountNotFound(id) => write!(f, "account {} not found", id),
+            Self::AccountIdsExhausted => write!(f, "no account ids available"),
+            Self::SameAccount(id) => write!(f, "account {} can not be used twice", id),
+            Self::OpenDisputes(id) => write!(f, "account {} has open disputes", id),
+            Self::TransactionNotFound(id) => write!(f, "transaction {} not found", id),
+            Self::AlreadyDisputed(id) => write!(f, "transaction {} is already disputed", id),
+            Self::ClientMismatch(id) => {
+                write!(f, "transaction {} belongs to a different client", id)
+            }
+            Self::NotDisputable(id) => write!(f, "transaction {} can not be disputed", id),
+            Self::AlreadyReversed(id) => write!(f, "transaction {} is already reversed", id),
+            Self::DisputeNotFound(id) => write!(f, "no open dispute for transaction {}", id),
+            Self::ExceedsDisputedAmount => write!(f, "amount exceeds the disputed amount"),
+            Self::ExceedsAccountLimit => write!(f, "withdrawal exceeds the account limit"),
+            Self::StorageFull => write!(f, "transaction storage is full"),
+            Self::AdjustmentsDisabled => write!(f, "adjustments are not enabled"),
+            Self::DuplicateTransaction(id) => write!(f, "transaction {} already exists", id),
+            Self::VelocityLimitExceeded(id) => {
+                write!(f, "client {} has too many recent transactions", id)
+            }
+            Self::DuplicateAccountId(id) => write!(f, "account {} already exists", id),
+            Self::Account(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StdError for BankError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Account(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<AccountError> for BankError {
+    fn from(other: AccountError) -> Self {
+        Self::Account(other)
+    }
+}
+
+/// Errors returned by `Amount::split`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitError {
+    /// An amount can not be split into zero parts.
+    ZeroParts,
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroParts => write!(f, "can not split into zero parts"),
+        }
+    }
+}
+
+impl StdError for SplitError {}
+
+/// Errors returned by `Amount::from_ratio`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RatioError {
+    /// The denominator is zero.
+    DivisionByZero,
+    /// The value can not be represented.
+    Overflow,
+}
+
+impl fmt::Display for RatioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::Overflow => write!(f, "amount overflow"),
+        }
+    }
+}
+
+impl StdError for RatioError {}
+
+/// Errors returned when parsing an `Amount` from a string.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseAmountError {
+    /// The string is not a decimal number.
+    Invalid,
+    /// The value can not be represented.
+    Overflow,
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid => write!(f, "invalid decimal amount"),
+            Self::Overflow => write!(f, "amount overflow"),
+        }
+    }
+}
+
+impl StdError for ParseAmountError {}
+
+/// Errors returned by `Bank::import_state_from_accounts_csv`. Lines are numbered from 1, including
+/// the header.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    /// The row could not be read, or does not have the expected columns.
+    MalformedRow { line: u64 },
+    /// The row has an amount which is not a valid decimal, a negative held amount, or a total which
+    /// does not match its available and held amounts.
+    InvalidAmount { line: u64 },
+    /// The client appears on more than one row.
+    DuplicateClient(AccountID),
+    /// The dispute does not reference a recorded deposit of its client.
+    UnknownTransaction(TransactionID),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MalformedRow { line } => write!(f, "malformed row on line {}", line),
+            Self::InvalidAmount { line } => write!(f, "invalid amount on line {}", line),
+            Self::DuplicateClient(id) => write!(f, "client {} appears more than once", id),
+            Self::UnknownTransaction(id) => write!(f, "transaction {} is not recorded", id),
+        }
+    }
+}
+
+impl StdError for ImportError {}
+
+/// Invariant violations found by `Bank::validate_consistency` and `Bank::reconcile`. These are
+/// never expected, and indicate a bug in the bank.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConsistencyError {
+    /// The account has negative held funds.
+    NegativeHeld(AccountID),
+    /// The account's available funds are below what its withdrawal limit allows.
+    BelowLimit(AccountID),
+    /// The account's held funds do not match the total of its open disputes.
+    HeldMismatch(AccountID),
+    /// An open dispute references a transaction which was never recorded.
+    DisputeWithoutTransaction(TransactionID),
+    /// The funds held by all accounts do not match the funds moved into the bank.
+    LedgerMismatch { expected: Amount, actual: Amount },
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NegativeHeld(id) => write!(f, "account {} has negative held funds", id),
+            Self::BelowLimit(id) => write!(f, "account {} is below its withdrawal limit", id),
+            Self::HeldMismatch(id) => {
+                write!(f, "account {} held funds do not match its disputes", id)
+            }
+            Self::DisputeWithoutTransaction(id) => {
+                write!(f, "dispute {} has no recorded transaction", id)
+            }
+            Self::LedgerMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "ledger expects {} but accounts hold {}",
+                    expected, actual
+                )
+            }
+        }
+    }
+}
+
+impl StdError for ConsistencyError {}
+
+/// Invariant violations of a single transaction found by `Bank::verify_transaction_integrity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegrityError {
+    /// The transaction is neither recorded nor in dispute.
+    TransactionNotFound(TransactionID),
+    /// The transaction is in dispute, but was never recorded.
+    DisputeWithoutTransaction(TransactionID),
+    /// The transaction has no amount, or an amount which is not positive.
+    InvalidAmount(TransactionID),
+    /// The client of the transaction has no account.
+    AccountNotFound(AccountID),
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TransactionNotFound(id) => write!(f, "transaction {} not found", id),
+            Self::DisputeWithoutTransaction(id) => {
+                write!(f, "dispute {} has no recorded transaction", id)
+            }
+            Self::InvalidAmount(id) => write!(f, "transaction {} has an invalid amount", id),
+            Self::AccountNotFound(id) => write!(f, "account {} not found", id),
+        }
+    }
+}
+
+impl StdError for IntegrityError {}
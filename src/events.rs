@@ -0,0 +1,52 @@
+use crate::{
+    amount::Amount,
+    bank::{AccountID, TransactionID},
+};
+
+/// Receives a notification for every operation successfully applied by a `Bank`, see
+/// `Bank::subscribe`. Disputes, resolves, and chargebacks are notified with the disputed amount.
+pub trait TransactionEventHandler {
+    fn on_deposit(&self, client: AccountID, tx_id: TransactionID, amount: Amount);
+    fn on_withdrawal(&self, client: AccountID, tx_id: TransactionID, amount: Amount);
+    fn on_dispute(&self, client: AccountID, tx_id: TransactionID, amount: Amount);
+    fn on_resolve(&self, client: AccountID, tx_id: TransactionID, amount: Amount);
+    fn on_chargeback(&self, client: AccountID, tx_id: TransactionID, amount: Amount);
+}
+
+// The handlers subscribed to a `Bank` along with their ids, in the order they were subscribed. A
+// cloned bank starts without any handlers, so that e.g. `Bank::simulate_transaction` does not
+// notify them.
+#[derive(Default)]
+pub(crate) struct EventHandlers {
+    handlers: Vec<(usize, Box<dyn TransactionEventHandler + Send>)>,
+
+    // Id for the next subscribed handler. Ids are never reused.
+    next_id: usize,
+}
+
+impl EventHandlers {
+    pub(crate) fn push(&mut self, handler: Box<dyn TransactionEventHandler + Send>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.handlers.push((id, handler));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: usize) -> bool {
+        let len = self.handlers.len();
+        self.handlers.retain(|(handler_id, _)| *handler_id != id);
+        self.handlers.len() != len
+    }
+
+    pub(crate) fn notify(&self, f: impl Fn(&dyn TransactionEventHandler)) {
+        for (_, handler) in &self.handlers {
+            f(handler.as_ref());
+        }
+    }
+}
+
+impl Clone for EventHandlers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
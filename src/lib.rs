@@ -0,0 +1,9 @@
+pub mod account;
+pub mod amount;
+pub mod audit;
+pub mod bank;
+pub mod clock;
+pub mod config;
+pub mod error;
+pub mod events;
+pub mod transaction;
@@ -1,15 +1,10 @@
-mod account;
-mod amount;
-mod bank;
 mod cli;
-mod error;
-mod transaction;
 
-use clap::Parser;
+use std::fs::File;
 
-use csv::ReaderBuilder;
+use clap::Parser;
 
-use crate::{bank::Bank, error::Error};
+use banker::{bank::Bank, error::Error};
 
 fn main() {
     let args = cli::Args::parse();
@@ -25,13 +20,8 @@ fn run(args: cli::Args) -> Result<(), Error> {
     let mut bank = Bank::new();
 
     // load input from csv
-    let mut reader = match ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .has_headers(true)
-        .flexible(true)
-        .from_path(args.file())
-    {
-        Ok(reader) => reader,
+    let file = match File::open(args.file()) {
+        Ok(file) => file,
         Err(e) => {
             return Err(Error::with_cause(
                 format!("Failed to create reader for: {:?}", args.file()),
@@ -40,20 +30,12 @@ fn run(args: cli::Args) -> Result<(), Error> {
         }
     };
 
-    // stream from the csv, processing each transaction one at a time
-    for result in reader.deserialize() {
-        let transaction: crate::transaction::Transaction = result?;
-        bank.process_transaction(transaction);
-    }
+    // stream from the csv, processing each transaction one at a time. Transactions that can not be
+    // applied are no-ops, see the assumptions in the README.
+    bank.import_transactions_csv(file)?;
 
     // stream to stdout
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(std::io::stdout());
-    writer.serialize(("client", "available", "held", "total", "locked"))?;
-    for row in bank.accounts_iter() {
-        writer.serialize(row)?;
-    }
+    bank.export_csv_to_stdout()?;
 
     Ok(())
 }
@@ -1,20 +1,55 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     amount::Amount,
     bank::{AccountID, TransactionID},
+    error::BankError,
 };
 
-/// A basic Transaction containing a type, client id, transaction number, and amount.
+/// A basic Transaction containing a type, client id, transaction number, amount, and optional memo.
+///
+/// For deposits, withdrawals, and adjustments the transaction number is the transaction's own id.
+/// Disputes, resolves, chargebacks, and admin reversals instead use it to reference the transaction
+/// they apply to, see `referenced_tx_id`. Their own id can be given separately in the optional
+/// `own_tx` column, see `own_id`.
 #[derive(Clone, Debug, Deserialize)]
 pub struct Transaction {
     r#type: Kind,
     client: AccountID,
     tx: TransactionID,
     amount: Option<Amount>,
+    #[serde(default)]
+    memo: Option<String>,
+    // The transaction's own id, for kinds where `tx` references another transaction.
+    #[serde(default)]
+    own_tx: Option<TransactionID>,
 }
 
 impl Transaction {
+    pub fn new(kind: Kind, client: AccountID, tx: TransactionID, amount: Option<Amount>) -> Self {
+        Self {
+            r#type: kind,
+            client,
+            tx,
+            amount,
+            memo: None,
+            own_tx: None,
+        }
+    }
+
+    /// Give a dispute-lifecycle transaction its own id, separate from the transaction it
+    /// references. Ignored for kinds which do not reference another transaction.
+    pub fn with_own_id(mut self, id: TransactionID) -> Self {
+        self.own_tx = Some(id);
+        self
+    }
+
+    /// Attach a free-form memo to the transaction, e.g. `"payroll March"`.
+    pub fn with_memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
     pub fn kind(&self) -> Kind {
         self.r#type
     }
@@ -27,13 +62,93 @@ impl Transaction {
         self.tx
     }
 
+    /// The transaction this one applies to, for disputes, resolves, chargebacks, and admin
+    /// reversals. `None` for kinds which do not reference another transaction.
+    pub fn referenced_tx_id(&self) -> Option<TransactionID> {
+        self.r#type.references_transaction().then_some(self.tx)
+    }
+
+    /// The transaction's own id. Transactions which reference another transaction only have their
+    /// own id if one was given through `with_own_id` or the `own_tx` column, and otherwise fall
+    /// back to the referenced id, as `id` does.
+    pub fn own_id(&self) -> TransactionID {
+        match self.own_tx {
+            Some(id) if self.r#type.references_transaction() => id,
+            _ => self.tx,
+        }
+    }
+
     pub fn amount(&self) -> Option<Amount> {
         self.amount
     }
+
+    /// The amount of the transaction, or zero for kinds without an amount such as disputes.
+    pub fn amount_or_zero(&self) -> Amount {
+        self.amount.unwrap_or_default()
+    }
+
+    pub fn has_amount(&self) -> bool {
+        self.amount.is_some()
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
+
+    /// Check the transaction on its own, without regard to any bank state: deposits, withdrawals,
+    /// and adjustments need an amount, which may only be negative for adjustments.
+    pub fn validate(&self) -> Result<(), BankError> {
+        match (self.r#type, self.amount) {
+            (Kind::Deposit | Kind::Withdrawal | Kind::Adjustment, None) => {
+                Err(BankError::MissingAmount)
+            }
+            (Kind::Deposit | Kind::Withdrawal, Some(amount)) if amount.is_negative() => {
+                Err(BankError::NegativeAmount)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn set_kind(&mut self, kind: Kind) {
+        self.r#type = kind;
+    }
+
+    pub(crate) fn set_client(&mut self, client: AccountID) {
+        self.client = client;
+    }
+
+    pub(crate) fn set_amount(&mut self, amount: Option<Amount>) {
+        self.amount = amount;
+    }
+}
+
+// Only valid combinations are generated: deposits, withdrawals, and adjustments always have an
+// amount, and other kinds never do. Clients and ids are drawn from small ranges so that disputes,
+// resolves, and chargebacks frequently reference earlier transactions.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Transaction {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let kind: Kind = u.arbitrary()?;
+        let amount = match kind {
+            Kind::Deposit | Kind::Withdrawal => Some(u.arbitrary()?),
+            Kind::Adjustment => {
+                let amount: Amount = u.arbitrary()?;
+                Some(if u.arbitrary()? { -amount } else { amount })
+            }
+            _ => None,
+        };
+        Ok(Self::new(
+            kind,
+            u.int_in_range(1..=10)?,
+            u.int_in_range(1..=100)?,
+            amount,
+        ))
+    }
 }
 
 /// Enum variant for the different types of transactions.
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 #[serde(rename_all = "snake_case")]
 pub enum Kind {
     Deposit,
@@ -41,4 +156,108 @@ pub enum Kind {
     Dispute,
     Resolve,
     Chargeback,
+    // Operator-initiated correction which undoes the referenced deposit or withdrawal.
+    AdminReversal,
+    // Regulatory correction which credits (positive amount) or debits (negative amount) the account
+    // directly, even if it is locked. Only applied if `BankConfig::allow_adjustments` is set.
+    Adjustment,
+}
+
+impl Kind {
+    // Kinds whose transaction number is the id of the transaction they apply to.
+    fn references_transaction(self) -> bool {
+        matches!(
+            self,
+            Kind::Dispute | Kind::Resolve | Kind::Chargeback | Kind::AdminReversal
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memo() {
+        let transaction =
+            Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into())).with_memo("payroll March");
+        assert_eq!(transaction.memo(), Some("payroll March"));
+
+        let transaction = Transaction::new(Kind::Deposit, 1, 1, Some(1.0.into()));
+        assert_eq!(transaction.memo(), None);
+    }
+
+    #[test]
+    fn validate() {
+        for kind in [Kind::Deposit, Kind::Withdrawal] {
+            assert_eq!(
+                Transaction::new(kind, 1, 1, Some(1.0.into())).validate(),
+                Ok(())
+            );
+            assert_eq!(
+                Transaction::new(kind, 1, 1, Some((-1.0).into())).validate(),
+                Err(BankError::NegativeAmount)
+            );
+            assert_eq!(
+                Transaction::new(kind, 1, 1, None).validate(),
+                Err(BankError::MissingAmount)
+            );
+        }
+        let adjustment = Transaction::new(Kind::Adjustment, 1, 1, Some((-1.0).into()));
+        assert_eq!(adjustment.validate(), Ok(()));
+        assert_eq!(
+            Transaction::new(Kind::Adjustment, 1, 1, None).validate(),
+            Err(BankError::MissingAmount)
+        );
+        assert_eq!(
+            Transaction::new(Kind::Dispute, 1, 1, None).validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn amount_or_zero() {
+        for kind in [Kind::Deposit, Kind::Withdrawal, Kind::Adjustment] {
+            let transaction = Transaction::new(kind, 1, 1, Some(2.5.into()));
+            assert!(transaction.has_amount());
+            assert_eq!(transaction.amount_or_zero(), 2.5.into());
+        }
+        for kind in [
+            Kind::Dispute,
+            Kind::Resolve,
+            Kind::Chargeback,
+            Kind::AdminReversal,
+        ] {
+            let transaction = Transaction::new(kind, 1, 1, None);
+            assert!(!transaction.has_amount());
+            assert_eq!(transaction.amount_or_zero(), Amount::new());
+        }
+    }
+
+    #[test]
+    fn referenced_tx_id() {
+        let deposit = Transaction::new(Kind::Deposit, 1, 7, Some(1.0.into())).with_own_id(9);
+        assert_eq!(deposit.referenced_tx_id(), None);
+        assert_eq!(deposit.own_id(), 7);
+
+        let dispute = Transaction::new(Kind::Dispute, 1, 7, None);
+        assert_eq!(dispute.referenced_tx_id(), Some(7));
+        assert_eq!(dispute.own_id(), 7);
+
+        let dispute = dispute.with_own_id(9);
+        assert_eq!(dispute.referenced_tx_id(), Some(7));
+        assert_eq!(dispute.own_id(), 9);
+        assert_eq!(dispute.id(), 7);
+    }
+
+    #[test]
+    fn deserialize_own_tx() {
+        let input = "type,client,tx,amount,own_tx\nresolve,1,7,,12\ndeposit,1,8,1.0,\n";
+        let mut reader = csv::Reader::from_reader(input.as_bytes());
+        let transactions: Vec<Transaction> = reader.deserialize().map(Result::unwrap).collect();
+
+        assert_eq!(transactions[0].referenced_tx_id(), Some(7));
+        assert_eq!(transactions[0].own_id(), 12);
+        assert_eq!(transactions[1].own_id(), 8);
+    }
 }
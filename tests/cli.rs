@@ -0,0 +1,31 @@
+use std::process::Command;
+
+// Run the binary on an example file, returning the rows it wrote to stdout after the header,
+// sorted since accounts are written in no particular order.
+fn run(example: &str) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_banker"))
+        .arg(format!(
+            "{}/examples/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            example
+        ))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("client,available,held,total,locked"));
+
+    let mut rows: Vec<String> = lines.map(str::to_string).collect();
+    rows.sort();
+    rows
+}
+
+#[test]
+fn basic_transactions() {
+    assert_eq!(
+        run("basic_transactions.csv"),
+        vec!["1,1.5,0.0,1.5,false", "2,2.0,0.0,2.0,false"]
+    );
+}
@@ -0,0 +1,21 @@
+#![cfg(feature = "arbitrary")]
+
+use arbitrary::{Arbitrary, Unstructured};
+use banker::{bank::Bank, transaction::Transaction};
+use proptest::prelude::*;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn random_transactions_reconcile(bytes in prop::collection::vec(any::<u8>(), 0..4096)) {
+        let mut u = Unstructured::new(&bytes);
+        let transactions = Vec::<Transaction>::arbitrary(&mut u).unwrap();
+
+        let mut bank = Bank::new();
+        bank.process_all(transactions);
+
+        prop_assert_eq!(bank.validate_consistency(), Ok(()));
+        prop_assert_eq!(bank.reconcile(), Ok(()));
+    }
+}